@@ -1,7 +1,67 @@
+use std::collections::HashMap;
+
 use crate::input;
+use crate::math;
 use crate::physics;
 use bevy::prelude::*;
 
+// shot used for speakers who don't ask for one
+pub const DEFAULT_SHOT: &str = "medium";
+// how quickly the camera eases towards a shot, higher is snappier
+const FRAMING_SPEED: f32 = 6.0;
+// close enough to the player's view to hand control back
+const RETURN_EPSILON: f32 = 0.005;
+
+// How the camera frames a speaker. `offset` is from the speaker, with x to the side, y up and
+// z back towards where the player stood, and `fov` is in degrees.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraShot {
+    pub offset: Vec3,
+    pub fov: f32,
+}
+
+// Resource with the shots dialogue can name with `camera`, for a character or a single node
+pub struct CameraShots(pub HashMap<std::string::String, CameraShot>);
+
+impl Default for CameraShots {
+    fn default() -> Self {
+        let shot = |offset, fov| CameraShot { offset, fov };
+        Self(HashMap::from([
+            (
+                DEFAULT_SHOT.to_string(),
+                shot(Vec3::new(0.0, 0.4, 2.5), 45.0),
+            ),
+            ("closeup".to_string(), shot(Vec3::new(0.4, 0.2, 1.6), 30.0)),
+            ("wide".to_string(), shot(Vec3::new(0.0, 1.0, 4.0), 60.0)),
+        ]))
+    }
+}
+
+// Points the camera at whoever is speaking, sent whenever the dialogue moves on
+pub struct FrameSpeakerEvent {
+    pub target: Entity,
+    // None for the default shot
+    pub shot: Option<std::string::String>,
+}
+
+struct PlayerView {
+    transform: Transform,
+    fov: f32,
+    rot_dir: Vec2,
+}
+
+// Resource tracking the camera while it's been taken away from the player
+#[derive(Default)]
+pub struct DialogueFraming {
+    // where the player was looking before the conversation, to return to afterwards
+    saved: Option<PlayerView>,
+    target: Option<Entity>,
+    shot: Option<std::string::String>,
+    // flat direction from the speaker towards where the player stood
+    facing: Vec3,
+    returning: bool,
+}
+
 #[derive(Component)]
 pub struct MainCamera;
 
@@ -34,10 +94,188 @@ pub fn startup_spawn_camera(mut commands: Commands) {
 
 pub fn camera_handle_input(
     inputs: Res<input::Inputs>,
+    framing: Res<DialogueFraming>,
     mut query: Query<(&mut physics::Velocity, &mut Transform), With<MainCamera>>,
 ) {
     let (mut cam_velocity, mut trans) = query.single_mut();
+    // still easing back to the player's view after a conversation
+    if framing.saved.is_some() {
+        cam_velocity.0 = Vec3::ZERO;
+        return;
+    }
     trans.rotation =
         Quat::from_rotation_y(inputs.rot_dir.x) * Quat::from_rotation_x(inputs.rot_dir.y);
     cam_velocity.0 = trans.rotation * -inputs.wish_dir;
 }
+
+// Where the camera sits to take a shot of something at `target`, seen from `facing`
+fn framed_view(target: Vec3, facing: Vec3, shot: &CameraShot) -> Transform {
+    let side = Vec3::Y.cross(facing).normalize_or_zero();
+    let position = target + side * shot.offset.x + Vec3::Y * shot.offset.y + facing * shot.offset.z;
+    Transform::from_translation(position).looking_at(target, Vec3::Y)
+}
+
+// Moves part of the way from `from` to `to`, the same fraction each second whatever the frame rate
+fn ease_towards(from: &Transform, to: &Transform, delta: f32) -> (Transform, f32) {
+    let t = 1.0 - (-FRAMING_SPEED * delta).exp();
+    (
+        Transform {
+            translation: from.translation.lerp(to.translation, t),
+            rotation: from.rotation.slerp(to.rotation, t),
+            scale: from.scale,
+        },
+        t,
+    )
+}
+
+pub fn save_player_view(
+    inputs: Res<input::Inputs>,
+    mut framing: ResMut<DialogueFraming>,
+    query: Query<(&Transform, &PerspectiveProjection), With<MainCamera>>,
+) {
+    framing.returning = false;
+    framing.target = None;
+    // talking again before the camera made it back keeps the original view
+    if framing.saved.is_some() {
+        return;
+    }
+    if let Ok((transform, projection)) = query.get_single() {
+        framing.saved = Some(PlayerView {
+            transform: *transform,
+            fov: projection.fov,
+            rot_dir: inputs.rot_dir,
+        });
+    }
+}
+
+// Eases the camera round to the shot asked for by the current speaker
+pub fn frame_speaker(
+    time: Res<Time>,
+    shots: Res<CameraShots>,
+    mut framing: ResMut<DialogueFraming>,
+    mut evr: EventReader<FrameSpeakerEvent>,
+    targets: Query<&GlobalTransform>,
+    mut camera_query: Query<(&mut Transform, &mut PerspectiveProjection), With<MainCamera>>,
+) {
+    let (mut transform, mut projection) = match camera_query.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    for e in evr.iter() {
+        if framing.target != Some(e.target) {
+            // shots are taken from the side of the speaker the player came from
+            if let Ok(target) = targets.get(e.target) {
+                let away = transform.translation - target.translation;
+                framing.facing = Vec3::new(away.x, 0.0, away.z)
+                    .try_normalize()
+                    .unwrap_or(Vec3::Z);
+            }
+        }
+        framing.target = Some(e.target);
+        framing.shot = e.shot.clone();
+    }
+
+    let target = match framing.target.and_then(|target| targets.get(target).ok()) {
+        Some(target) => target,
+        None => return,
+    };
+    // unknown shots were reported when the dialogue file loaded
+    let name = framing.shot.as_deref().unwrap_or(DEFAULT_SHOT);
+    let shot = match shots.0.get(name).or_else(|| shots.0.get(DEFAULT_SHOT)) {
+        Some(shot) => shot,
+        None => return,
+    };
+    let view = framed_view(target.translation, framing.facing, shot);
+    let (eased, t) = ease_towards(&transform, &view, time.delta_seconds());
+    *transform = eased;
+    let fov = shot.fov * math::DEG_TO_RAD;
+    projection.fov += (fov - projection.fov) * t;
+}
+
+pub fn return_to_player_view(mut framing: ResMut<DialogueFraming>) {
+    framing.returning = true;
+}
+
+// Eases the camera back to where the player left it, then hands control back
+pub fn ease_back_to_player_view(
+    time: Res<Time>,
+    mut inputs: ResMut<input::Inputs>,
+    mut framing: ResMut<DialogueFraming>,
+    mut camera_query: Query<(&mut Transform, &mut PerspectiveProjection), With<MainCamera>>,
+) {
+    if !framing.returning {
+        return;
+    }
+    let saved = match &framing.saved {
+        Some(saved) => saved,
+        None => return,
+    };
+    let (mut transform, mut projection) = match camera_query.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let (eased, t) = ease_towards(&transform, &saved.transform, time.delta_seconds());
+    *transform = eased;
+    projection.fov += (saved.fov - projection.fov) * t;
+
+    let arrived = transform.translation.distance(saved.transform.translation) < RETURN_EPSILON
+        && transform.rotation.angle_between(saved.transform.rotation) < RETURN_EPSILON
+        && (projection.fov - saved.fov).abs() < RETURN_EPSILON;
+    if arrived {
+        transform.translation = saved.transform.translation;
+        transform.rotation = saved.transform.rotation;
+        projection.fov = saved.fov;
+        // looking around during the conversation shouldn't turn the player afterwards
+        inputs.rot_dir = saved.rot_dir;
+        framing.saved = None;
+        framing.returning = false;
+    }
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraShots>()
+            .init_resource::<DialogueFraming>()
+            .add_event::<FrameSpeakerEvent>()
+            .add_startup_system(startup_spawn_camera)
+            .add_system(ease_back_to_player_view.after("input").before("physics"))
+            .add_system_set(
+                SystemSet::on_update(crate::AppState::Game)
+                    .with_system(camera_handle_input.after("input").before("physics")),
+            )
+            .add_system_set(
+                SystemSet::on_enter(crate::AppState::Dialogue).with_system(save_player_view),
+            )
+            .add_system_set(
+                SystemSet::on_update(crate::AppState::Dialogue)
+                    .with_system(frame_speaker.after("dialogue-update")),
+            )
+            // covers conversations which end normally and ones which never got going
+            .add_system_set(
+                SystemSet::on_exit(crate::AppState::Dialogue).with_system(return_to_player_view),
+            );
+    }
+}
+
+#[test]
+pub fn test_framed_view() {
+    let shot = CameraShot {
+        offset: Vec3::new(1.0, 0.5, 2.0),
+        fov: 45.0,
+    };
+    let view = framed_view(Vec3::Y, Vec3::Z, &shot);
+    // x is to the player's right, looking down -z at the speaker
+    assert!(view.translation.abs_diff_eq(Vec3::new(1.0, 1.5, 2.0), 1e-5));
+    let forward = view.rotation * -Vec3::Z;
+    assert!(forward.abs_diff_eq((Vec3::Y - view.translation).normalize(), 1e-5));
+
+    // easing covers the same ground whatever the frame rate
+    let from = Transform::identity();
+    let to = Transform::from_xyz(1.0, 0.0, 0.0);
+    let (once, _) = ease_towards(&from, &to, 0.1);
+    let (half, _) = ease_towards(&from, &to, 0.05);
+    let (twice, _) = ease_towards(&half, &to, 0.05);
+    assert!(once.translation.abs_diff_eq(twice.translation, 1e-5));
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+};
+use yaml_rust::{Yaml, YamlLoader};
+
+use super::characters::{self, Character};
+use super::commands;
+use super::error::{DialogueLoadError, DialogueLoadErrorKind};
+use super::tree::{self, DialogueGraph};
+use super::variables::DialogueValue;
+
+// Every stage of a .dialogue.yaml file. Stages which failed to parse are left out and their
+// errors kept, so one broken stage doesn't stop the rest of the file from being used.
+#[derive(Debug, Default, TypeUuid)]
+#[uuid = "40532403-cbd7-469f-9714-b67d558cca84"]
+pub struct DialogueAsset {
+    pub file: std::string::String,
+    pub stages: HashMap<std::string::String, DialogueGraph>,
+    // everyone who can speak in the file, by id
+    pub characters: HashMap<std::string::String, Character>,
+    pub errors: Vec<DialogueLoadError>,
+}
+
+impl DialogueAsset {
+    pub fn from_yaml(source: &str, file: &str) -> Self {
+        let mut asset = Self {
+            file: file.to_string(),
+            ..Default::default()
+        };
+        let docs = match YamlLoader::load_from_str(source) {
+            Ok(docs) => docs,
+            Err(e) => {
+                asset.errors.push(
+                    DialogueLoadError::new("", DialogueLoadErrorKind::Syntax(e.to_string()))
+                        .in_file(file),
+                );
+                return asset;
+            }
+        };
+        let stages = match docs.get(0).and_then(|doc| doc.as_hash()) {
+            Some(stages) => stages,
+            None => {
+                asset
+                    .errors
+                    .push(DialogueLoadError::expected("", "a map of stages").in_file(file));
+                return asset;
+            }
+        };
+
+        match characters::parse_characters(&docs[0][characters::CHARACTERS_KEY]) {
+            Ok(characters) => asset.characters = characters,
+            Err(e) => asset.errors.push(e.in_file(file)),
+        }
+
+        for (stage, stage_yaml) in stages {
+            let stage = match stage.as_str() {
+                Some(characters::CHARACTERS_KEY) => continue,
+                Some(stage) => stage,
+                None => {
+                    asset
+                        .errors
+                        .push(DialogueLoadError::expected("", "string stage names").in_file(file));
+                    continue;
+                }
+            };
+            match tree::parse_stage_yaml(stage, stage_yaml, &asset.characters) {
+                Ok(graph) => {
+                    asset.stages.insert(stage.to_string(), graph);
+                }
+                Err(e) => asset.errors.push(e.in_file(file)),
+            }
+        }
+
+        // transitions may only lead to stages in the same file
+        for (stage, graph) in asset.stages.iter() {
+            for (i, transition) in graph.transitions.iter().enumerate() {
+                if transition.to == characters::CHARACTERS_KEY
+                    || !stages.contains_key(&Yaml::String(transition.to.clone()))
+                {
+                    asset.errors.push(
+                        DialogueLoadError::new(
+                            format!("{}.transitions[{}].to", stage, i),
+                            DialogueLoadErrorKind::UnknownStage,
+                        )
+                        .in_file(file)
+                        .in_stage(stage),
+                    );
+                }
+            }
+            // and so may set_stage actions
+            for action in graph.actions() {
+                if action.command != commands::SET_STAGE {
+                    continue;
+                }
+                let path = format!("{}.{}", action.path, commands::SET_STAGE);
+                let error = match &action.arg {
+                    Some(DialogueValue::String(to))
+                        if to != characters::CHARACTERS_KEY
+                            && stages.contains_key(&Yaml::String(to.clone())) =>
+                    {
+                        continue
+                    }
+                    Some(DialogueValue::String(_)) => {
+                        DialogueLoadError::new(path, DialogueLoadErrorKind::UnknownStage)
+                    }
+                    _ => DialogueLoadError::expected(path, "the name of a stage"),
+                };
+                asset.errors.push(error.in_file(file).in_stage(stage));
+            }
+        }
+        asset
+    }
+
+    // Errors for every camera shot named in the file which `is_known` doesn't recognise
+    pub fn check_camera_shots(&self, is_known: impl Fn(&str) -> bool) -> Vec<DialogueLoadError> {
+        let mut errors = Vec::new();
+        let unknown = |path: std::string::String, shot: &Option<std::string::String>| {
+            shot.as_ref().filter(|shot| !is_known(shot)).map(|shot| {
+                DialogueLoadError::new(path, DialogueLoadErrorKind::UnknownCameraShot(shot.clone()))
+                    .in_file(&self.file)
+            })
+        };
+        for (id, character) in self.characters.iter() {
+            let path = format!("{}.{}.camera", characters::CHARACTERS_KEY, id);
+            errors.extend(unknown(path, &character.camera));
+        }
+        for (stage, graph) in self.stages.iter() {
+            for node in graph.nodes.values() {
+                let path = format!("{}.camera", node.path);
+                errors.extend(unknown(path, &node.camera).map(|e| e.in_stage(stage)));
+            }
+        }
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        errors
+    }
+}
+
+#[derive(Default)]
+pub struct DialogueAssetLoader;
+
+impl AssetLoader for DialogueAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let file = load_context.path().to_string_lossy().to_string();
+            let source = std::str::from_utf8(bytes)?;
+            load_context
+                .set_default_asset(LoadedAsset::new(DialogueAsset::from_yaml(source, &file)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dialogue.yaml"]
+    }
+}
+
+#[cfg(test)]
+use super::stages::{StageTransition, StageTrigger};
+
+#[cfg(test)]
+fn load_test_asset(path: &str) -> DialogueAsset {
+    DialogueAsset::from_yaml(&std::fs::read_to_string(path).unwrap(), path)
+}
+
+#[test]
+pub fn test_dialogue_asset_from_yaml() {
+    let asset = load_test_asset("./assets/dialogue/test.dialogue.yaml");
+    assert!(asset.errors.is_empty());
+    let graph = &asset.stages["default"];
+    let node = graph.node(&graph.start).unwrap();
+    assert_eq!(node.id, "hi");
+    assert_eq!(node.lines[0].text, "Hi");
+    assert_eq!(node.responses[0].text, "Hello");
+
+    let busy = graph
+        .node(node.responses[0].next.as_ref().unwrap())
+        .unwrap();
+    assert_eq!(busy.id, "default.dialogue.responses[0].dialogue");
+    assert_eq!(busy.lines[0].text, "I can't talk now.");
+    assert_eq!(busy.responses[0].text, "Oh..");
+    assert_eq!(busy.responses[0].next, None);
+    // loops back to the start
+    assert_eq!(busy.responses[1].next.as_deref(), Some("hi"));
+
+    assert_eq!(node.responses[1].text, "Goodbye");
+    assert_eq!(node.responses[1].next.as_deref(), Some("bye"));
+    assert_eq!(graph.node("bye").unwrap().lines[0].text, "See you.");
+}
+
+#[test]
+pub fn test_game_dialogue_loads() {
+    let asset = load_test_asset("./assets/dialogue/cube.dialogue.yaml");
+    assert_eq!(asset.errors, vec![]);
+    assert!(asset.stages.contains_key("default"));
+    assert_eq!(
+        asset.stages["default"].transitions[0],
+        StageTransition {
+            to: "stage_too_long".to_string(),
+            trigger: StageTrigger::Elapsed(30.0),
+            condition: None,
+        }
+    );
+}
+
+#[test]
+pub fn test_broken_stage_is_reported() {
+    let asset = DialogueAsset::from_yaml(
+        "
+good:
+  dialogue:
+    text: Hi
+    responses: []
+  transitions:
+    - to: nowhere
+      on_complete: true
+  nodes:
+    angry:
+      text: Hmph.
+      actions:
+        - set_stage: gone
+bad:
+  dialogue:
+    responses: []
+",
+        "broken.dialogue.yaml",
+    );
+    assert!(asset.stages.contains_key("good"));
+    assert!(!asset.stages.contains_key("bad"));
+    assert_eq!(
+        asset.errors[0].to_string(),
+        "broken.dialogue.yaml (stage 'bad') at bad.dialogue.text: expected a string"
+    );
+    assert_eq!(
+        asset.errors[1].to_string(),
+        "broken.dialogue.yaml (stage 'good') at good.transitions[0].to: no such stage"
+    );
+    assert_eq!(
+        asset.errors[2].to_string(),
+        "broken.dialogue.yaml (stage 'good') at good.nodes.angry.actions[0].set_stage: \
+         no such stage"
+    );
+}
+
+#[test]
+pub fn test_unknown_camera_shots_are_reported() {
+    let asset = DialogueAsset::from_yaml(
+        "
+characters:
+  cube:
+    camera: closeup
+default:
+  dialogue:
+    speaker: cube
+    camera: dutch_angle
+    text: Hi
+",
+        "cameras.dialogue.yaml",
+    );
+    let errors = asset.check_camera_shots(|shot| shot == "closeup");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].to_string(),
+        "cameras.dialogue.yaml (stage 'default') at default.dialogue.camera: \
+         no camera shot named 'dutch_angle'"
+    );
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use yaml_rust::yaml;
+
+use super::asset::DialogueAsset;
+use super::characters::Character;
+use super::error::{DialogueLoadError, DialogueLoadErrorKind};
+use super::expr::Expr;
+use super::source::DialogueSource;
+use super::stages::StageEvent;
+use super::tree;
+use super::variables::DialogueVariables;
+use crate::{camera, ui};
+
+const DEFAULT_DURATION: f32 = 4.0;
+const DEFAULT_COOLDOWN: f32 = 10.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BarkTrigger {
+    // the player came within this many units
+    Near(f32),
+    // a StageEvent with this name was sent
+    Event(std::string::String),
+}
+
+// A line said in passing, without stopping the game. Declared in a stage's yaml under `barks`,
+// e.g.
+//   - text: Mind the edge.
+//     near: 3
+//     cooldown: 20
+//   - text: "[wave]Whoops.[/wave]"
+//     on_event: player_fell
+//     duration: 2
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bark {
+    pub text: std::string::String,
+    // id of the character saying it, None for the one barking
+    pub speaker: Option<std::string::String>,
+    pub trigger: BarkTrigger,
+    // must also hold for the bark to be said
+    pub condition: Option<Expr>,
+    // seconds the bubble stays up for
+    pub duration: f32,
+    // seconds before the bark can be said again
+    pub cooldown: f32,
+}
+
+pub fn parse_barks(
+    yaml: &yaml::Yaml,
+    path: &str,
+    characters: &HashMap<std::string::String, Character>,
+) -> Result<Vec<Bark>, DialogueLoadError> {
+    let barks = match yaml {
+        yaml::Yaml::BadValue => return Ok(Vec::new()),
+        yaml::Yaml::Array(barks) => barks,
+        _ => return Err(DialogueLoadError::expected(path, "a list of barks")),
+    };
+    barks
+        .iter()
+        .enumerate()
+        .map(|(i, bark)| {
+            let path = format!("{}[{}]", path, i);
+            let text = bark["text"]
+                .as_str()
+                .ok_or_else(|| DialogueLoadError::expected(format!("{}.text", path), "a string"))?;
+            let speaker = tree::optional_str(bark, "speaker", &path)?;
+            if let Some(speaker) = speaker {
+                if !characters.contains_key(speaker) {
+                    return Err(DialogueLoadError::new(
+                        format!("{}.speaker", path),
+                        DialogueLoadErrorKind::UnknownCharacter(speaker.to_string()),
+                    ));
+                }
+            }
+
+            let trigger = match (
+                tree::optional_number(bark, "near", &path)?,
+                tree::optional_str(bark, "on_event", &path)?,
+            ) {
+                (Some(radius), None) => BarkTrigger::Near(radius),
+                (None, Some(event)) => BarkTrigger::Event(event.to_string()),
+                _ => return Err(DialogueLoadError::expected(path, "one of near or on_event")),
+            };
+            Ok(Bark {
+                text: text.to_string(),
+                speaker: speaker.map(|s| s.to_string()),
+                trigger,
+                condition: tree::optional_expr(bark, "if", &path)?,
+                duration: tree::optional_number(bark, "duration", &path)?
+                    .unwrap_or(DEFAULT_DURATION),
+                cooldown: tree::optional_number(bark, "cooldown", &path)?
+                    .unwrap_or(DEFAULT_COOLDOWN),
+            })
+        })
+        .collect()
+}
+
+// Kept by each DialogueSource for the barks of its current stage, by index
+#[derive(Default)]
+pub struct BarkState {
+    // seconds since startup each bark was last said
+    last_said: HashMap<usize, f64>,
+    // `near` barks the player was in range of last frame
+    in_range: HashSet<usize>,
+}
+
+impl BarkState {
+    pub fn clear(&mut self) {
+        self.last_said.clear();
+        self.in_range.clear();
+    }
+
+    fn is_ready(&self, index: usize, bark: &Bark, now: f64) -> bool {
+        self.last_said
+            .get(&index)
+            .map_or(true, |said| now - said >= bark.cooldown as f64)
+    }
+
+    // Only true on the frame the player comes into range, not for as long as they stay there
+    fn entered_range(&mut self, index: usize, near: bool) -> bool {
+        if near {
+            self.in_range.insert(index)
+        } else {
+            self.in_range.remove(&index);
+            false
+        }
+    }
+}
+
+// Has anyone with barks in their current stage say the first one which was triggered
+#[allow(clippy::too_many_arguments)]
+pub fn trigger_barks(
+    time: Res<Time>,
+    dialogue_assets: Res<Assets<DialogueAsset>>,
+    vars: Res<DialogueVariables>,
+    mut stage_evr: EventReader<StageEvent>,
+    player_query: Query<&GlobalTransform, With<camera::MainCamera>>,
+    mut sources: Query<(Entity, &GlobalTransform, &mut DialogueSource)>,
+    mut evw: EventWriter<ui::ShowBarkEvent>,
+) {
+    let now = time.seconds_since_startup();
+    let player = player_query.single().translation;
+    let stage_events: Vec<&StageEvent> = stage_evr.iter().collect();
+    for (entity, transform, mut source) in sources.iter_mut() {
+        let asset = match dialogue_assets.get(&source.handle) {
+            Some(asset) => asset,
+            None => continue,
+        };
+        let barks = match asset.stages.get(&source.stage) {
+            Some(graph) => &graph.barks,
+            None => continue,
+        };
+        let distance = player.distance(transform.translation);
+
+        let mut said = None;
+        for (i, bark) in barks.iter().enumerate() {
+            let triggered = match &bark.trigger {
+                BarkTrigger::Near(radius) => source.barks.entered_range(i, distance <= *radius),
+                BarkTrigger::Event(name) => stage_events
+                    .iter()
+                    .any(|e| e.name == *name && e.target.map_or(true, |target| target == entity)),
+            };
+            if said.is_none()
+                && triggered
+                && source.barks.is_ready(i, bark, now)
+                && bark.condition.iter().all(|c| c.is_true(&vars))
+            {
+                said = Some(i);
+            }
+        }
+
+        if let Some(i) = said {
+            let bark = &barks[i];
+            source.barks.last_said.insert(i, now);
+            let character = bark
+                .speaker
+                .as_ref()
+                .and_then(|speaker| asset.characters.get(speaker));
+            evw.send(ui::ShowBarkEvent {
+                target: entity,
+                speaker: character.map(|character| ui::SpeakerNameData {
+                    name: character.name.clone(),
+                    color: character.color,
+                }),
+                text: bark.text.clone(),
+                duration: bark.duration,
+            });
+        }
+    }
+}
+
+#[test]
+pub fn test_barks() {
+    let asset = DialogueAsset::from_yaml(
+        "
+characters:
+  cube: {}
+default:
+  dialogue:
+    text: Hi
+  barks:
+    - text: Mind the edge.
+      speaker: cube
+      near: 3
+    - text: Whoops.
+      on_event: player_fell
+      duration: 2.5
+      cooldown: 0
+bad:
+  dialogue:
+    text: Hi
+  barks:
+    - text: Both?
+      near: 3
+      on_event: player_fell
+",
+        "barks.dialogue.yaml",
+    );
+    let barks = &asset.stages["default"].barks;
+    assert_eq!(barks[0].trigger, BarkTrigger::Near(3.0));
+    assert_eq!(barks[0].speaker.as_deref(), Some("cube"));
+    assert_eq!(barks[0].duration, DEFAULT_DURATION);
+    assert_eq!(barks[1].trigger, BarkTrigger::Event("player_fell".into()));
+    assert_eq!(barks[1].duration, 2.5);
+    assert_eq!(
+        asset.errors[0].to_string(),
+        "barks.dialogue.yaml (stage 'bad') at bad.barks[0]: expected one of near or on_event"
+    );
+
+    let mut state = BarkState::default();
+    assert!(state.entered_range(0, true));
+    // staying in range doesn't say it again
+    assert!(!state.entered_range(0, true));
+    assert!(!state.entered_range(0, false));
+    assert!(state.entered_range(0, true));
+
+    state.last_said.insert(0, 1.0);
+    assert!(!state.is_ready(0, &barks[0], 10.0));
+    assert!(state.is_ready(0, &barks[0], 11.0));
+    assert!(state.is_ready(1, &barks[1], 1.0));
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use yaml_rust::yaml;
+
+use super::error::DialogueLoadError;
+use super::tree;
+
+// top-level key of a dialogue file holding its characters rather than a stage
+pub const CHARACTERS_KEY: &str = "characters";
+
+const DEFAULT_NAME_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+// Someone who can be given lines with a node's `speaker`, declared once per file, e.g.
+//   characters:
+//     cube:
+//       name: The Cube
+//       color: "#8fd3ff"
+//       portrait: cube
+//       camera: closeup
+#[derive(Clone, Debug, PartialEq)]
+pub struct Character {
+    pub name: std::string::String,
+    pub color: Color,
+    // shown beside the character's lines unless they pick another expression
+    pub portrait: Option<std::string::String>,
+    // camera shot framing the character while they talk
+    pub camera: Option<std::string::String>,
+}
+
+// Marks the entity playing a character, so the camera can turn to whoever is speaking
+#[derive(Component)]
+pub struct DialogueCharacter(pub std::string::String);
+
+pub fn parse_characters(
+    yaml: &yaml::Yaml,
+) -> Result<HashMap<std::string::String, Character>, DialogueLoadError> {
+    let entries = match yaml {
+        yaml::Yaml::BadValue => return Ok(HashMap::new()),
+        yaml::Yaml::Hash(entries) => entries,
+        _ => {
+            return Err(DialogueLoadError::expected(
+                CHARACTERS_KEY,
+                "a map of character ids to characters",
+            ))
+        }
+    };
+    let mut characters = HashMap::new();
+    for (id, character) in entries {
+        let id = id
+            .as_str()
+            .ok_or_else(|| DialogueLoadError::expected(CHARACTERS_KEY, "string character ids"))?;
+        let path = format!("{}.{}", CHARACTERS_KEY, id);
+        let name = tree::optional_str(character, "name", &path)?.unwrap_or(id);
+        let color = match tree::optional_str(character, "color", &path)? {
+            Some(hex) => Color::hex(hex.trim_start_matches('#')).map_err(|_| {
+                DialogueLoadError::expected(format!("{}.color", path), "a hex color")
+            })?,
+            None => DEFAULT_NAME_COLOR,
+        };
+        characters.insert(
+            id.to_string(),
+            Character {
+                name: name.to_string(),
+                color,
+                portrait: tree::optional_str(character, "portrait", &path)?.map(|p| p.to_string()),
+                camera: tree::optional_str(character, "camera", &path)?.map(|c| c.to_string()),
+            },
+        );
+    }
+    Ok(characters)
+}
+
+#[cfg(test)]
+use super::asset::DialogueAsset;
+
+#[test]
+pub fn test_node_speakers() {
+    let asset = DialogueAsset::from_yaml(
+        "
+characters:
+  cube:
+    name: The Cube
+    color: '#ff0000'
+    portrait: cube
+    camera: closeup
+  sphere: {}
+default:
+  dialogue:
+    speaker: cube
+    lines:
+      - Have you met my friend?
+      - text: They're a bit shy.
+        portrait: cube/worried
+    responses:
+      - text: No?
+        dialogue:
+          speaker: sphere
+          portrait: sphere/shy
+          camera: wide
+          text: Hello.
+          responses: []
+bad:
+  dialogue:
+    speaker: pyramid
+    text: ...
+    responses: []
+",
+        "characters.dialogue.yaml",
+    );
+    assert!(!asset.stages.contains_key(CHARACTERS_KEY));
+    assert_eq!(asset.characters["cube"].name, "The Cube");
+    assert_eq!(asset.characters["cube"].color, Color::rgb(1.0, 0.0, 0.0));
+    assert_eq!(asset.characters["sphere"].name, "sphere");
+
+    let graph = &asset.stages["default"];
+    let start = &graph.nodes[&graph.start];
+    assert_eq!(start.speaker.as_deref(), Some("cube"));
+    let friend = &graph.nodes["default.dialogue.responses[0].dialogue"];
+    assert_eq!(friend.speaker.as_deref(), Some("sphere"));
+
+    // portraits come from the line, then the node, then the character
+    let cube = asset.characters.get("cube");
+    assert_eq!(start.portrait(0, cube), Some("cube"));
+    assert_eq!(start.portrait(1, cube), Some("cube/worried"));
+    assert_eq!(
+        friend.portrait(0, asset.characters.get("sphere")),
+        Some("sphere/shy")
+    );
+    assert_eq!(start.portrait(0, asset.characters.get("sphere")), None);
+    // and camera shots from the node, then the character
+    assert_eq!(start.camera(cube), Some("closeup"));
+    assert_eq!(friend.camera(cube), Some("wide"));
+    assert_eq!(start.camera(asset.characters.get("sphere")), None);
+
+    assert_eq!(
+        asset.errors[0].to_string(),
+        "characters.dialogue.yaml (stage 'bad') at bad.dialogue.speaker: \
+         speaker references unknown character 'pyramid'"
+    );
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use bevy::{
+    app::Events,
+    ecs::system::{Command, SystemParam},
+    prelude::*,
+};
+use yaml_rust::yaml;
+
+use super::asset::DialogueAsset;
+use super::error::{DialogueLoadError, DialogueLoadErrorKind};
+use super::source::DialogueSource;
+use super::stages::StageChanged;
+use super::variables::DialogueValue;
+
+// One entry of a node's or response's `actions` list, e.g. `- give_item: key` or `- shake`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogueAction {
+    pub command: std::string::String,
+    pub arg: Option<DialogueValue>,
+    // yaml path of the action, for reporting unknown commands
+    pub path: std::string::String,
+}
+
+pub fn parse_actions(
+    yaml: &yaml::Yaml,
+    path: &str,
+) -> Result<Vec<DialogueAction>, DialogueLoadError> {
+    let path = format!("{}.actions", path);
+    let actions = match yaml {
+        yaml::Yaml::BadValue => return Ok(Vec::new()),
+        yaml::Yaml::Array(actions) => actions,
+        _ => return Err(DialogueLoadError::expected(path, "a list of actions")),
+    };
+    actions
+        .iter()
+        .enumerate()
+        .map(|(i, action)| {
+            let path = format!("{}[{}]", path, i);
+            let (command, arg) = match action {
+                yaml::Yaml::String(command) => (command.clone(), None),
+                yaml::Yaml::Hash(entries) if entries.len() == 1 => {
+                    let (command, arg) = entries.iter().next().unwrap();
+                    let command = command
+                        .as_str()
+                        .ok_or_else(|| DialogueLoadError::expected(&path, "a command name"))?;
+                    let arg = match arg {
+                        yaml::Yaml::Null => None,
+                        arg => Some(DialogueValue::from_yaml(arg).ok_or_else(|| {
+                            DialogueLoadError::expected(
+                                format!("{}.{}", path, command),
+                                "a bool, number or string argument",
+                            )
+                        })?),
+                    };
+                    (command.to_string(), arg)
+                }
+                _ => {
+                    return Err(DialogueLoadError::expected(
+                        path,
+                        "a command name, or a single `command: argument` pair",
+                    ))
+                }
+            };
+            Ok(DialogueAction { command, arg, path })
+        })
+        .collect()
+}
+
+pub struct DialogueCommandContext {
+    // who the player is talking to
+    pub speaker: Entity,
+    pub arg: Option<DialogueValue>,
+}
+
+pub type DialogueCommandHandler = fn(&mut World, &DialogueCommandContext);
+
+// Resource mapping command names used in dialogue files to the game code that runs them
+pub struct DialogueCommands {
+    handlers: HashMap<std::string::String, DialogueCommandHandler>,
+}
+
+impl Default for DialogueCommands {
+    fn default() -> Self {
+        let mut commands = Self {
+            handlers: HashMap::new(),
+        };
+        commands.register(SET_STAGE, set_stage);
+        commands
+    }
+}
+
+impl DialogueCommands {
+    pub fn register(&mut self, name: &str, handler: DialogueCommandHandler) {
+        self.handlers.insert(name.to_string(), handler);
+    }
+
+    pub fn get(&self, name: &str) -> Option<DialogueCommandHandler> {
+        self.handlers.get(name).copied()
+    }
+
+    // Errors for every action in the asset naming a command nobody registered
+    pub fn check(&self, asset: &DialogueAsset, file: &str) -> Vec<DialogueLoadError> {
+        let mut errors = Vec::new();
+        for (stage, graph) in asset.stages.iter() {
+            for action in graph.actions() {
+                if self.get(&action.command).is_none() {
+                    errors.push(
+                        DialogueLoadError::new(
+                            action.path.as_str(),
+                            DialogueLoadErrorKind::UnknownCommand(action.command.clone()),
+                        )
+                        .in_file(file)
+                        .in_stage(stage),
+                    );
+                }
+            }
+        }
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        errors
+    }
+}
+
+pub trait DialogueAppExt {
+    fn add_dialogue_command(&mut self, name: &str, handler: DialogueCommandHandler) -> &mut Self;
+}
+
+impl DialogueAppExt for App {
+    fn add_dialogue_command(&mut self, name: &str, handler: DialogueCommandHandler) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(DialogueCommands::default)
+            .register(name, handler);
+        self
+    }
+}
+
+struct RunDialogueCommand {
+    handler: DialogueCommandHandler,
+    context: DialogueCommandContext,
+}
+
+impl Command for RunDialogueCommand {
+    fn write(self, world: &mut World) {
+        (self.handler)(world, &self.context);
+    }
+}
+
+// Queues the handlers for a list of actions, to run at the end of the stage
+#[derive(SystemParam)]
+pub struct DialogueActions<'w, 's> {
+    commands: Commands<'w, 's>,
+    registry: Res<'w, DialogueCommands>,
+}
+
+impl<'w, 's> DialogueActions<'w, 's> {
+    pub fn run(&mut self, speaker: Entity, actions: &[DialogueAction]) {
+        for action in actions {
+            match self.registry.get(&action.command) {
+                Some(handler) => self.commands.add(RunDialogueCommand {
+                    handler,
+                    context: DialogueCommandContext {
+                        speaker,
+                        arg: action.arg.clone(),
+                    },
+                }),
+                // already reported when the file loaded
+                None => warn!("skipping unknown dialogue command '{}'", action.command),
+            }
+        }
+    }
+}
+
+// `- set_stage: angry` moves the speaker on to another stage of their file, which is checked
+// when the file loads
+pub const SET_STAGE: &str = "set_stage";
+
+fn set_stage(world: &mut World, context: &DialogueCommandContext) {
+    let stage = match &context.arg {
+        Some(DialogueValue::String(stage)) => stage.clone(),
+        _ => {
+            warn!("set_stage needs the name of a stage");
+            return;
+        }
+    };
+    let from = match world.get_mut::<DialogueSource>(context.speaker) {
+        Some(mut source) => {
+            let from = source.stage.clone();
+            source.set_stage(&stage);
+            from
+        }
+        None => return,
+    };
+    world
+        .get_resource_mut::<Events<StageChanged>>()
+        .unwrap()
+        .send(StageChanged {
+            speaker: context.speaker,
+            from,
+            to: stage,
+        });
+}
+
+#[test]
+pub fn test_unknown_commands_are_reported() {
+    let asset = DialogueAsset::from_yaml(
+        "
+default:
+  dialogue:
+    text: Hi
+    actions:
+      - wave
+    responses:
+      - text: Take this.
+        actions:
+          - give_item: key
+          - set_stage: default
+",
+        "actions.dialogue.yaml",
+    );
+    assert!(asset.errors.is_empty());
+    let node = &asset.stages["default"].nodes["default.dialogue"];
+    assert_eq!(node.actions[0].command, "wave");
+    assert_eq!(node.actions[0].arg, None);
+    assert_eq!(
+        node.responses[0].actions[0].arg,
+        Some(DialogueValue::String("key".to_string()))
+    );
+
+    let mut registry = DialogueCommands::default();
+    registry.register("wave", |_, _| {});
+    let errors = registry.check(&asset, &asset.file);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].to_string(),
+        "actions.dialogue.yaml (stage 'default') at default.dialogue.responses[0].actions[0]: \
+         no dialogue command named 'give_item'"
+    );
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DialogueLoadErrorKind {
+    // the asset server hasn't finished loading the file yet
+    NotLoaded,
+    // the file isn't valid yaml
+    Syntax(std::string::String),
+    UnknownStage,
+    // a value was missing or had the wrong type, holds a description of what was expected
+    Expected(&'static str),
+    // a goto or goodbye pointing at a node id which doesn't exist in the stage
+    UnknownNode(std::string::String),
+    DuplicateNode(std::string::String),
+    // a condition that couldn't be parsed, holds the reason
+    Expression(std::string::String),
+    // an action naming a command no handler was registered for
+    UnknownCommand(std::string::String),
+    // a node's speaker missing from the file's characters
+    UnknownCharacter(std::string::String),
+    // a node's or character's `camera` naming a shot the game doesn't have
+    UnknownCameraShot(std::string::String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogueLoadError {
+    pub file: std::string::String,
+    pub stage: Option<std::string::String>,
+    // dotted yaml path to the offending value, e.g. default.dialogue.responses[1].text
+    pub path: std::string::String,
+    pub kind: DialogueLoadErrorKind,
+}
+
+impl DialogueLoadError {
+    pub fn new(path: impl Into<std::string::String>, kind: DialogueLoadErrorKind) -> Self {
+        Self {
+            file: std::string::String::new(),
+            stage: None,
+            path: path.into(),
+            kind,
+        }
+    }
+
+    pub fn expected(path: impl Into<std::string::String>, what: &'static str) -> Self {
+        Self::new(path, DialogueLoadErrorKind::Expected(what))
+    }
+
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.file = file.to_string();
+        self
+    }
+
+    pub fn in_stage(mut self, stage: &str) -> Self {
+        self.stage = Some(stage.to_string());
+        self
+    }
+}
+
+impl fmt::Display for DialogueLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(stage) = &self.stage {
+            write!(f, " (stage '{}')", stage)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, " at {}", self.path)?;
+        }
+        match &self.kind {
+            DialogueLoadErrorKind::NotLoaded => write!(f, ": not loaded yet"),
+            DialogueLoadErrorKind::Syntax(e) => write!(f, ": invalid yaml: {}", e),
+            DialogueLoadErrorKind::UnknownStage => write!(f, ": no such stage"),
+            DialogueLoadErrorKind::Expected(what) => write!(f, ": expected {}", what),
+            DialogueLoadErrorKind::UnknownNode(id) => {
+                write!(f, ": references unknown node '{}'", id)
+            }
+            DialogueLoadErrorKind::DuplicateNode(id) => {
+                write!(f, ": node id '{}' is already used", id)
+            }
+            DialogueLoadErrorKind::Expression(e) => write!(f, ": invalid condition: {}", e),
+            DialogueLoadErrorKind::UnknownCommand(name) => {
+                write!(f, ": no dialogue command named '{}'", name)
+            }
+            DialogueLoadErrorKind::UnknownCharacter(id) => {
+                write!(f, ": speaker references unknown character '{}'", id)
+            }
+            DialogueLoadErrorKind::UnknownCameraShot(name) => {
+                write!(f, ": no camera shot named '{}'", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DialogueLoadError {}
+
+// Sent by the dialogue plugin whenever a dialogue file fails to load
+pub struct DialogueLoadFailed(pub DialogueLoadError);
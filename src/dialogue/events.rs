@@ -0,0 +1,75 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+pub struct DialogueStarted {
+    pub speaker: Entity,
+}
+
+pub struct DialogueNodeEntered {
+    pub speaker: Entity,
+    pub node_id: std::string::String,
+}
+
+pub struct ResponseChosen {
+    pub speaker: Entity,
+    // node the response was chosen from
+    pub node_id: std::string::String,
+    pub response_index: usize,
+    // as shown on the button, without markup
+    pub text: std::string::String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DialogueEndReason {
+    // a response with nowhere to go was chosen
+    Finished,
+    // there was nothing left worth saying
+    Exhausted,
+    // the player walked away, after the stage's goodbye node if it has one
+    PlayerLeft,
+}
+
+pub struct DialogueEnded {
+    pub speaker: Entity,
+    pub reason: DialogueEndReason,
+}
+
+// Everything needed to move a conversation along, so every way in and out of dialogue is
+// announced to the rest of the game
+#[derive(SystemParam)]
+pub struct DialogueEvents<'w, 's> {
+    started: EventWriter<'w, 's, DialogueStarted>,
+    node_entered: EventWriter<'w, 's, DialogueNodeEntered>,
+    response_chosen: EventWriter<'w, 's, ResponseChosen>,
+    ended: EventWriter<'w, 's, DialogueEnded>,
+    app_state: ResMut<'w, State<crate::AppState>>,
+}
+
+impl<'w, 's> DialogueEvents<'w, 's> {
+    pub fn start(&mut self, speaker: Entity, node_id: &str) {
+        self.started.send(DialogueStarted { speaker });
+        self.enter_node(speaker, node_id);
+    }
+
+    pub fn enter_node(&mut self, speaker: Entity, node_id: &str) {
+        self.node_entered.send(DialogueNodeEntered {
+            speaker,
+            node_id: node_id.to_string(),
+        });
+    }
+
+    pub fn choose_response(&mut self, event: ResponseChosen) {
+        self.response_chosen.send(event);
+    }
+
+    // Several systems can end a conversation in the same frame, only the first one counts
+    pub fn end(&mut self, speaker: Entity, reason: DialogueEndReason) {
+        if crate::queue_state(&mut self.app_state, crate::AppState::ClosingDialogue) {
+            self.ended.send(DialogueEnded { speaker, reason });
+        }
+    }
+
+    // Leaves dialogue without a conversation having started, e.g. when it failed to load
+    pub fn abort(&mut self) {
+        crate::queue_state(&mut self.app_state, crate::AppState::Game);
+    }
+}
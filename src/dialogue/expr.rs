@@ -0,0 +1,268 @@
+// A tiny expression language for dialogue conditions, e.g. `met_cube && trust >= 2`.
+//
+// expr    := and ('||' and)*
+// and     := unary ('&&' unary)*
+// unary   := '!' unary | compare
+// compare := primary (('==' | '!=' | '<' | '<=' | '>' | '>=') primary)?
+// primary := number | 'string' | "string" | true | false | variable | '(' expr ')'
+
+use std::cmp::Ordering;
+
+use super::variables::{DialogueValue, DialogueVariables};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Value(DialogueValue),
+    Variable(std::string::String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, CompareOp, Box<Expr>),
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Expr, std::string::String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    // Unset variables evaluate to None, which is false and never equal to anything
+    pub fn eval(&self, vars: &DialogueVariables) -> Option<DialogueValue> {
+        match self {
+            Expr::Value(value) => Some(value.clone()),
+            Expr::Variable(name) => vars.get(name).cloned(),
+            Expr::Not(expr) => Some(DialogueValue::Bool(!expr.is_true(vars))),
+            Expr::And(a, b) => Some(DialogueValue::Bool(a.is_true(vars) && b.is_true(vars))),
+            Expr::Or(a, b) => Some(DialogueValue::Bool(a.is_true(vars) || b.is_true(vars))),
+            Expr::Compare(a, op, b) => {
+                let ordering = match (a.eval(vars), b.eval(vars)) {
+                    (Some(a), Some(b)) => compare_values(&a, &b),
+                    _ => None,
+                };
+                Some(DialogueValue::Bool(match (op, ordering) {
+                    (CompareOp::Ne, None) => true,
+                    (_, None) => false,
+                    (CompareOp::Eq, Some(o)) => o == Ordering::Equal,
+                    (CompareOp::Ne, Some(o)) => o != Ordering::Equal,
+                    (CompareOp::Lt, Some(o)) => o == Ordering::Less,
+                    (CompareOp::Le, Some(o)) => o != Ordering::Greater,
+                    (CompareOp::Gt, Some(o)) => o == Ordering::Greater,
+                    (CompareOp::Ge, Some(o)) => o != Ordering::Less,
+                }))
+            }
+        }
+    }
+
+    pub fn is_true(&self, vars: &DialogueVariables) -> bool {
+        self.eval(vars).map_or(false, |value| value.is_truthy())
+    }
+}
+
+// None when the values can't be compared, e.g. a string and a number
+fn compare_values(a: &DialogueValue, b: &DialogueValue) -> Option<Ordering> {
+    match (a, b) {
+        (DialogueValue::Int(a), DialogueValue::Int(b)) => Some(a.cmp(b)),
+        (DialogueValue::String(a), DialogueValue::String(b)) => Some(a.cmp(b)),
+        (DialogueValue::Bool(a), DialogueValue::Bool(b)) if a == b => Some(Ordering::Equal),
+        (DialogueValue::Bool(_), DialogueValue::Bool(_)) => None,
+        (a, b) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Value(DialogueValue),
+    Ident(std::string::String),
+    Not,
+    And,
+    Or,
+    Compare(CompareOp),
+    LParen,
+    RParen,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, std::string::String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Compare(CompareOp::Eq), 2),
+            ('!', Some('=')) => (Token::Compare(CompareOp::Ne), 2),
+            ('<', Some('=')) => (Token::Compare(CompareOp::Le), 2),
+            ('>', Some('=')) => (Token::Compare(CompareOp::Ge), 2),
+            ('<', _) => (Token::Compare(CompareOp::Lt), 1),
+            ('>', _) => (Token::Compare(CompareOp::Gt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('\'' | '"', _) => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| "unterminated string".to_string())?;
+                let s: std::string::String = chars[i + 1..i + 1 + end].iter().collect();
+                (Token::Value(DialogueValue::String(s)), end + 2)
+            }
+            (c, _)
+                if c.is_ascii_digit()
+                    || (c == '-' && next.map_or(false, |n| n.is_ascii_digit())) =>
+            {
+                let len = 1 + chars[i + 1..]
+                    .iter()
+                    .take_while(|ch| ch.is_ascii_digit() || **ch == '.')
+                    .count();
+                let s: std::string::String = chars[i..i + len].iter().collect();
+                let value = if s.contains('.') {
+                    DialogueValue::Float(s.parse().map_err(|_| format!("bad number '{}'", s))?)
+                } else {
+                    DialogueValue::Int(s.parse().map_err(|_| format!("bad number '{}'", s))?)
+                };
+                (Token::Value(value), len)
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|ch| ch.is_alphanumeric() || **ch == '_' || **ch == '.')
+                    .count();
+                let s: std::string::String = chars[i..i + len].iter().collect();
+                let token = match s.as_str() {
+                    "true" => Token::Value(DialogueValue::Bool(true)),
+                    "false" => Token::Value(DialogueValue::Bool(false)),
+                    _ => Token::Ident(s),
+                };
+                (token, len)
+            }
+            (c, _) => return Err(format!("unexpected character '{}'", c)),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, std::string::String> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, std::string::String> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, std::string::String> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_compare()
+    }
+
+    fn parse_compare(&mut self) -> Result<Expr, std::string::String> {
+        let lhs = self.parse_primary()?;
+        if let Some(Token::Compare(op)) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            return Ok(Expr::Compare(
+                Box::new(lhs),
+                op,
+                Box::new(self.parse_primary()?),
+            ));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, std::string::String> {
+        match self.next() {
+            Some(Token::Value(value)) => Ok(Expr::Value(value)),
+            Some(Token::Ident(name)) => Ok(Expr::Variable(name)),
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[test]
+pub fn test_expressions() {
+    let mut vars = DialogueVariables::default();
+    let met_and_trusted = Expr::parse("met_cube && trust >= 2").unwrap();
+    assert!(!met_and_trusted.is_true(&vars));
+
+    vars.set("met_cube", DialogueValue::Bool(true));
+    vars.set("trust", DialogueValue::Int(1));
+    assert!(!met_and_trusted.is_true(&vars));
+    vars.set("trust", DialogueValue::Float(2.5));
+    assert!(met_and_trusted.is_true(&vars));
+
+    vars.set("name", DialogueValue::String("cube".into()));
+    assert!(Expr::parse("name == 'cube' || (false)")
+        .unwrap()
+        .is_true(&vars));
+    assert!(Expr::parse("!(name != \"cube\") && !unset")
+        .unwrap()
+        .is_true(&vars));
+    assert!(Expr::parse("unset != 1").unwrap().is_true(&vars));
+    assert!(!Expr::parse("trust > -3 && name < 5")
+        .unwrap()
+        .is_true(&vars));
+
+    assert!(Expr::parse("trust >=").is_err());
+    assert!(Expr::parse("(a && b").is_err());
+    assert!(Expr::parse("a b").is_err());
+    assert!(Expr::parse("a & b").is_err());
+}
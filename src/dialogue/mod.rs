@@ -1,50 +1,388 @@
-use crate::ui;
+use crate::{camera, input, interact, ui};
 use bevy::prelude::*;
+mod asset;
+mod barks;
+mod characters;
+mod commands;
+mod error;
+mod events;
+mod expr;
+mod source;
 mod stages;
+mod transcript;
 mod tree;
+mod variables;
+
+pub use asset::DialogueAsset;
+pub use characters::{Character, DialogueCharacter};
+pub use commands::{DialogueAppExt, DialogueCommandContext, DialogueCommands};
+pub use error::{DialogueLoadError, DialogueLoadFailed};
+pub use events::{
+    DialogueEndReason, DialogueEnded, DialogueNodeEntered, DialogueStarted, ResponseChosen,
+};
+pub use source::DialogueSource;
+pub use stages::{StageChanged, StageEvent};
+pub use transcript::{DialogueTranscript, TranscriptEntry, TranscriptSpeaker};
+pub use variables::{DialogueValue, DialogueVariables};
+
+// Resource for tweaking how dialogue behaves
+pub struct DialogueSettings {
+    // free the mouse to click responses, turn off to use only the keyboard or a gamepad
+    pub unlock_cursor: bool,
+}
+
+impl Default for DialogueSettings {
+    fn default() -> Self {
+        Self {
+            unlock_cursor: true,
+        }
+    }
+}
+
+pub fn unlock_dialogue_cursor(settings: Res<DialogueSettings>, windows: ResMut<Windows>) {
+    if settings.unlock_cursor {
+        crate::unlock_cursor(windows);
+    }
+}
 
 pub fn on_response_chosen(
     mut evr: EventReader<ui::ResponseButtonClicked>,
     mut dialogue_tree: ResMut<tree::DialogueTree>,
-    mut app_state: ResMut<State<super::AppState>>,
+    mut sources: Query<&mut DialogueSource>,
+    mut vars: ResMut<DialogueVariables>,
+    mut dialogue_events: events::DialogueEvents,
+    mut actions: commands::DialogueActions,
 ) {
     for e in evr.iter() {
-        // Pull the relevant response node out of its vec
-        let response_node = dialogue_tree.root.responses.swap_remove(e.0);
+        // e.g. chosen in the same frame the player left for the goodbye node, or before a
+        // reload took the response away
+        if !dialogue_tree.on_last_line() || e.0.node != dialogue_tree.current {
+            continue;
+        }
+        let response = match dialogue_tree
+            .current_node()
+            .and_then(|node| node.responses.get(e.0.index))
+        {
+            Some(response) => response.clone(),
+            None => continue,
+        };
+        let speaker = match dialogue_tree.speaker {
+            Some(speaker) => speaker,
+            None => continue,
+        };
+        let mut source = match sources.get_mut(speaker) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+        dialogue_events.choose_response(ResponseChosen {
+            speaker,
+            node_id: dialogue_tree.current.clone(),
+            response_index: e.0.index,
+            text: ui::strip_markup(&response.text),
+        });
+        if let Some(visited_id) = response.visited_id {
+            source.visited.insert(visited_id);
+        }
+        for effect in response.effects.iter() {
+            vars.apply(effect);
+        }
+        actions.run(speaker, &response.actions);
 
-        if let Some(node) = response_node.dialogue_node {
-            // if there's new dialogue associated with the response, set the root dialogue to this
-            dialogue_tree.root = node;
+        let next = match response.next {
+            Some(next) => next,
+            None => {
+                // drop dialogue entirely if there's nothing else to be said
+                dialogue_events.end(speaker, end_reason(&dialogue_tree));
+                break;
+            }
+        };
+        let exhausted = dialogue_tree.graph.node(&next).map_or(true, |node| {
+            dialogue_tree
+                .graph
+                .node_is_exhausted(node, &source.visited, &vars)
+        });
+        if exhausted {
+            dialogue_events.end(speaker, DialogueEndReason::Exhausted);
+            break;
         } else {
-            // drop dialogue entirely if there's nothing else to be said
-            app_state.set(super::AppState::Game).unwrap();
+            // if the response leads somewhere, follow it, even back to a node we've seen
+            dialogue_events.enter_node(speaker, &next);
+            dialogue_tree.enter(next);
+            if let Some(node) = dialogue_tree.current_node() {
+                actions.run(speaker, &node.actions);
+            }
+        }
+    }
+}
+
+// Steps through the lines of the current node, and out of the conversation after the last line
+// of a node with nothing to respond with. Text still being typed out is finished first.
+pub fn advance_dialogue(
+    inputs: Res<input::Inputs>,
+    mut reveal: ResMut<ui::TextReveal>,
+    mut dialogue_tree: ResMut<tree::DialogueTree>,
+    mut dialogue_events: events::DialogueEvents,
+) {
+    if !inputs.continue_dialogue {
+        return;
+    }
+    if !reveal.is_finished() {
+        reveal.finish();
+        return;
+    }
+    let speaker = match dialogue_tree.speaker {
+        Some(speaker) => speaker,
+        None => return,
+    };
+    if !dialogue_tree.on_last_line() {
+        dialogue_tree.line += 1;
+    } else if dialogue_tree
+        .current_node()
+        .map_or(false, |node| node.responses.is_empty())
+    {
+        dialogue_events.end(speaker, end_reason(&dialogue_tree));
+    }
+}
+
+// How a conversation which ran its course ended, leaving through the goodbye node still counts
+// as the player leaving
+fn end_reason(dialogue_tree: &tree::DialogueTree) -> DialogueEndReason {
+    if dialogue_tree.leaving {
+        DialogueEndReason::PlayerLeft
+    } else {
+        DialogueEndReason::Finished
+    }
+}
+
+// Lets the player walk away from any node which isn't locked, through the stage's goodbye node
+// if it has one. Leaving again from the goodbye node ends the conversation there and then.
+pub fn leave_dialogue(
+    inputs: Res<input::Inputs>,
+    mut dialogue_tree: ResMut<tree::DialogueTree>,
+    mut dialogue_events: events::DialogueEvents,
+    mut actions: commands::DialogueActions,
+) {
+    if !inputs.exit_dialogue {
+        return;
+    }
+    let speaker = match dialogue_tree.speaker {
+        Some(speaker) => speaker,
+        None => return,
+    };
+    if dialogue_tree
+        .current_node()
+        .map_or(false, |node| node.locked)
+    {
+        return;
+    }
+    match dialogue_tree.graph.goodbye.clone() {
+        Some(goodbye) if !dialogue_tree.leaving => {
+            dialogue_tree.leaving = true;
+            dialogue_events.enter_node(speaker, &goodbye);
+            dialogue_tree.enter(goodbye);
+            if let Some(node) = dialogue_tree.current_node() {
+                actions.run(speaker, &node.actions);
+            }
         }
+        _ => dialogue_events.end(speaker, DialogueEndReason::PlayerLeft),
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn setup_dialogue(
     mut dialogue_tree: ResMut<tree::DialogueTree>,
-    dialogue_stage: Res<stages::DialogueStage>,
+    interaction: Res<interact::Interaction>,
+    mut sources: Query<&mut DialogueSource>,
+    vars: Res<DialogueVariables>,
+    dialogue_assets: Res<Assets<DialogueAsset>>,
+    mut dialogue_events: events::DialogueEvents,
+    mut actions: commands::DialogueActions,
+    mut evw: EventWriter<DialogueLoadFailed>,
+    mut stage_evw: EventWriter<StageChanged>,
+    mut theme_evw: EventWriter<ui::UseDialogueTheme>,
+) {
+    let speaker = interaction
+        .current_interaction
+        .and_then(|entity| sources.get_mut(entity).ok().map(|source| (entity, source)));
+    let (speaker, mut source) = match speaker {
+        Some(speaker) => speaker,
+        None => {
+            warn!("entered dialogue without anyone to talk to");
+            dialogue_events.abort();
+            return;
+        }
+    };
+
+    theme_evw.send(ui::UseDialogueTheme(source.theme.clone()));
+
+    let mut result = dialogue_tree.start(speaker, &source, &dialogue_assets);
+    if result.is_ok()
+        && source.stage != stages::DEFAULT_STAGE
+        && dialogue_tree.current_is_exhausted(&source.visited, &vars)
+    {
+        // nothing more to say in this stage, move back to the default one
+        stage_evw.send(StageChanged {
+            speaker,
+            from: source.stage.clone(),
+            to: stages::DEFAULT_STAGE.to_string(),
+        });
+        source.set_stage(stages::DEFAULT_STAGE);
+        result = dialogue_tree.load_stage(stages::DEFAULT_STAGE, &dialogue_assets);
+    }
+
+    if let Err(e) = result {
+        // a broken dialogue file shouldn't take the game down with it
+        error!("failed to load dialogue: {}", e);
+        evw.send(DialogueLoadFailed(e));
+        dialogue_events.abort();
+        return;
+    }
+
+    dialogue_events.start(speaker, &dialogue_tree.current);
+    if dialogue_tree.current_is_exhausted(&source.visited, &vars) {
+        dialogue_events.end(speaker, DialogueEndReason::Exhausted);
+    } else if let Some(node) = dialogue_tree.current_node() {
+        actions.run(speaker, &node.actions);
+    }
+}
+
+// Lets `on_complete` stage transitions know a conversation was had, walking away or running
+// out of things to say doesn't count
+pub fn finish_dialogue(
+    dialogue_tree: Res<tree::DialogueTree>,
+    mut ended: EventReader<DialogueEnded>,
+    mut sources: Query<&mut DialogueSource>,
+) {
+    let finished = ended.iter().any(|e| {
+        Some(e.speaker) == dialogue_tree.speaker && e.reason == DialogueEndReason::Finished
+    });
+    if !finished {
+        return;
+    }
+    if let Some(mut source) = dialogue_tree.speaker.and_then(|s| sources.get_mut(s).ok()) {
+        if source.stage == dialogue_tree.stage {
+            source.completed = true;
+        }
+    }
+}
+
+// Reports problems in (re)loaded dialogue files, and re-syncs an open conversation with any
+// edits made to its file while the game is running
+pub fn sync_dialogue_assets(
+    mut asset_evr: EventReader<AssetEvent<DialogueAsset>>,
+    dialogue_assets: Res<Assets<DialogueAsset>>,
+    mut dialogue_tree: ResMut<tree::DialogueTree>,
+    registry: Res<DialogueCommands>,
+    shots: Res<camera::CameraShots>,
+    app_state: Res<State<super::AppState>>,
+    mut evw: EventWriter<DialogueLoadFailed>,
 ) {
-    dialogue_tree.load_stage(&dialogue_stage.stage);
+    for e in asset_evr.iter() {
+        let (handle, modified) = match e {
+            AssetEvent::Created { handle } => (handle, false),
+            AssetEvent::Modified { handle } => (handle, true),
+            AssetEvent::Removed { .. } => continue,
+        };
+        if let Some(asset) = dialogue_assets.get(handle) {
+            let unknown_commands = registry.check(asset, &asset.file);
+            let unknown_shots = asset.check_camera_shots(|shot| shots.0.contains_key(shot));
+            for err in asset
+                .errors
+                .iter()
+                .chain(unknown_commands.iter())
+                .chain(unknown_shots.iter())
+            {
+                error!("failed to load dialogue: {}", err);
+                evw.send(DialogueLoadFailed(err.clone()));
+            }
+        }
+
+        if modified
+            && *handle == dialogue_tree.handle
+            && *app_state.current() == super::AppState::Dialogue
+        {
+            if let Err(err) = dialogue_tree.reload(&dialogue_assets) {
+                // keep the conversation going on the old version of the dialogue
+                error!("failed to reload dialogue: {}", err);
+                evw.send(DialogueLoadFailed(err));
+            }
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn update_dialogue(
     dialogue_tree: ResMut<tree::DialogueTree>,
+    sources: Query<&DialogueSource>,
+    vars: Res<DialogueVariables>,
+    dialogue_assets: Res<Assets<DialogueAsset>>,
     mut evw: EventWriter<ui::UpdateDialogueUIEvent>,
+    mut camera_evw: EventWriter<camera::FrameSpeakerEvent>,
+    transcript: Res<DialogueTranscript>,
+    characters: Query<(Entity, &DialogueCharacter)>,
 ) {
-    if dialogue_tree.is_changed() {
+    if !dialogue_tree.is_changed() {
+        return;
+    }
+    let source = match dialogue_tree.speaker.and_then(|s| sources.get(s).ok()) {
+        Some(source) => source,
+        None => return,
+    };
+    if let Some(npc) = dialogue_tree.speaker {
+        // whoever says the node's lines, if they're in the world, else the one being talked to
+        let speaker = dialogue_tree
+            .current_node()
+            .and_then(|node| node.speaker.as_ref())
+            .and_then(|id| {
+                characters
+                    .iter()
+                    .find(|(_, character)| character.0 == *id)
+                    .map(|(entity, _)| entity)
+            })
+            .unwrap_or(npc);
+        camera_evw.send(camera::FrameSpeakerEvent {
+            target: speaker,
+            shot: dialogue_tree
+                .current_camera(&dialogue_assets)
+                .map(|shot| shot.to_string()),
+        });
+    }
+    if let (Some(node), Some(line)) = (dialogue_tree.current_node(), dialogue_tree.current_line()) {
+        let last_line = dialogue_tree.on_last_line();
+        let speaker = dialogue_tree
+            .current_character(&dialogue_assets)
+            .map(|character| ui::SpeakerNameData {
+                name: character.name.clone(),
+                color: character.color,
+            });
         evw.send(ui::UpdateDialogueUIEvent {
-            dialogue_text: dialogue_tree.root.text.clone(),
-            response_buttons: dialogue_tree
-                .root
+            speaker,
+            portrait: dialogue_tree
+                .current_portrait(&dialogue_assets)
+                .map(|portrait| portrait.to_string()),
+            dialogue_text: line.to_string(),
+            // responses wait until the whole node has been read
+            can_continue: !last_line || node.responses.is_empty(),
+            response_buttons: node
                 .responses
                 .iter()
                 .enumerate()
                 .map(|(i, response)| ui::ResponseButtonElementData {
                     text: response.text.clone(),
-                    id: i,
-                    skip: false,
+                    id: ui::ResponseId {
+                        node: node.id.clone(),
+                        index: i,
+                    },
+                    // hide responses until the last line, and those which have nothing
+                    // left to offer or whose conditions don't hold
+                    skip: !last_line
+                        || !dialogue_tree
+                            .graph
+                            .response_is_shown(response, &source.visited, &vars),
+                    seen: match (dialogue_tree.speaker, &response.next) {
+                        (Some(npc), Some(next)) => transcript.has_seen(npc, next),
+                        _ => false,
+                    },
                 })
                 .collect(),
         })
@@ -55,23 +393,56 @@ pub struct DialoguePlugin;
 
 impl Plugin for DialoguePlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<tree::DialogueTree>()
-            .init_resource::<stages::DialogueStage>()
+        app.add_asset::<DialogueAsset>()
+            .init_asset_loader::<asset::DialogueAssetLoader>()
+            .init_resource::<tree::DialogueTree>()
+            .init_resource::<DialogueVariables>()
+            .init_resource::<DialogueCommands>()
+            .init_resource::<DialogueSettings>()
+            .init_resource::<DialogueTranscript>()
+            .add_event::<DialogueLoadFailed>()
+            .add_event::<DialogueStarted>()
+            .add_event::<DialogueNodeEntered>()
+            .add_event::<ResponseChosen>()
+            .add_event::<DialogueEnded>()
+            .add_system(sync_dialogue_assets)
+            .add_event::<StageEvent>()
+            .add_event::<StageChanged>()
+            .add_system(stages::progress_stages)
             .add_system_set(
-                SystemSet::on_update(super::AppState::Game).with_system(stages::progress_stages),
+                SystemSet::on_update(super::AppState::Game).with_system(barks::trigger_barks),
             )
+            // outside the dialogue state to catch the response which ends a conversation
+            .add_system(transcript::record_transcript.after("dialogue-respond"))
             .add_system_set(
                 SystemSet::on_enter(super::AppState::Dialogue)
                     .with_system(setup_dialogue)
-                    .with_system(crate::unlock_cursor),
+                    .with_system(unlock_dialogue_cursor),
             )
             .add_system_set(
                 SystemSet::on_update(super::AppState::Dialogue)
-                    .with_system(on_response_chosen)
-                    .with_system(update_dialogue),
+                    // a response's first line mustn't be skipped by the same press
+                    .with_system(
+                        on_response_chosen
+                            .label("dialogue-respond")
+                            .after("dialogue-advance"),
+                    )
+                    .with_system(advance_dialogue.label("dialogue-advance"))
+                    .with_system(
+                        leave_dialogue
+                            .before("dialogue-advance")
+                            .before("dialogue-update"),
+                    )
+                    .with_system(
+                        update_dialogue
+                            .label("dialogue-update")
+                            .after("dialogue-respond"),
+                    ),
             )
             .add_system_set(
-                SystemSet::on_exit(super::AppState::Dialogue).with_system(crate::lock_cursor),
+                SystemSet::on_exit(super::AppState::Dialogue)
+                    .with_system(finish_dialogue)
+                    .with_system(crate::lock_cursor),
             );
     }
 }
@@ -0,0 +1,55 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use super::asset::DialogueAsset;
+use super::barks::BarkState;
+use crate::ui;
+
+// Attached to anything the player can talk to, pointing at the file its conversations live in
+#[derive(Component)]
+pub struct DialogueSource {
+    pub file: std::string::String,
+    pub handle: Handle<DialogueAsset>,
+    // stage the next conversation with this entity starts from
+    pub stage: std::string::String,
+    // visited_ids of the once-only responses already chosen in this stage
+    pub visited: HashSet<std::string::String>,
+    // seconds spent in the current stage outside of dialogue
+    pub stage_time: f32,
+    // whether a conversation has been finished in the current stage
+    pub completed: bool,
+    // cooldowns and ranges of the current stage's barks
+    pub barks: BarkState,
+    // look of the dialogue box while talking to this entity, None for the default
+    pub theme: Option<Handle<ui::DialogueThemeFile>>,
+}
+
+impl DialogueSource {
+    pub fn new(asset_server: &AssetServer, file: &str, stage: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            handle: asset_server.load(file),
+            stage: stage.to_string(),
+            visited: HashSet::new(),
+            stage_time: 0.0,
+            completed: false,
+            barks: BarkState::default(),
+            theme: None,
+        }
+    }
+
+    pub fn with_theme(mut self, asset_server: &AssetServer, theme: &str) -> Self {
+        self.theme = Some(asset_server.load(theme));
+        self
+    }
+
+    // Moving to a new stage starts its responses and progression afresh
+    pub fn set_stage(&mut self, stage: &str) {
+        self.stage = stage.to_string();
+        self.visited.clear();
+        self.stage_time = 0.0;
+        self.completed = false;
+        self.barks.clear();
+    }
+}
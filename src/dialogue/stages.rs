@@ -1,21 +1,180 @@
 use bevy::prelude::*;
+use yaml_rust::yaml;
 
-pub struct DialogueStage {
-    pub stage: std::string::String,
-    timer: Timer,
+use super::asset::DialogueAsset;
+use super::error::DialogueLoadError;
+use super::expr::Expr;
+use super::source::DialogueSource;
+use super::tree;
+use super::variables::DialogueVariables;
+
+// stage every character falls back to once they've said all they can
+pub const DEFAULT_STAGE: &str = "default";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StageTrigger {
+    // seconds spent in the stage while out of dialogue
+    Elapsed(f32),
+    // fires as soon as the transition's condition holds
+    Condition,
+    // a conversation in this stage has finished
+    ConversationEnded,
+    // a StageEvent with this name was sent
+    Event(std::string::String),
 }
 
-impl FromWorld for DialogueStage {
-    fn from_world(_: &mut World) -> DialogueStage {
-        Self {
-            stage: "default".into(),
-            timer: Timer::from_seconds(30.0, false),
-        }
-    }
+// Declared in a stage's yaml under `transitions`, e.g.
+//   - to: stage_too_long
+//     after: 30
+//   - to: angry
+//     if: insulted_cube
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageTransition {
+    pub to: std::string::String,
+    pub trigger: StageTrigger,
+    // must also hold for the trigger to take effect
+    pub condition: Option<Expr>,
 }
 
-pub fn progress_stages(mut stage: ResMut<DialogueStage>, time: Res<Time>) {
-    if stage.timer.tick(time.delta()).just_finished() {
-        stage.stage = "stage_too_long".to_string();
+pub fn parse_transitions(
+    yaml: &yaml::Yaml,
+    path: &str,
+) -> Result<Vec<StageTransition>, DialogueLoadError> {
+    let transitions = match yaml {
+        yaml::Yaml::BadValue => return Ok(Vec::new()),
+        yaml::Yaml::Array(transitions) => transitions,
+        _ => return Err(DialogueLoadError::expected(path, "a list of transitions")),
+    };
+    transitions
+        .iter()
+        .enumerate()
+        .map(|(i, transition)| {
+            let path = format!("{}[{}]", path, i);
+            let to = transition["to"]
+                .as_str()
+                .ok_or_else(|| DialogueLoadError::expected(format!("{}.to", path), "a stage"))?;
+            let condition = tree::optional_expr(transition, "if", &path)?;
+
+            let trigger = if let Some(seconds) = transition["after"]
+                .as_f64()
+                .or_else(|| transition["after"].as_i64().map(|s| s as f64))
+            {
+                StageTrigger::Elapsed(seconds as f32)
+            } else if !transition["after"].is_badvalue() {
+                return Err(DialogueLoadError::expected(
+                    format!("{}.after", path),
+                    "a number of seconds",
+                ));
+            } else if tree::optional_bool(transition, "on_complete", &path)? == Some(true) {
+                StageTrigger::ConversationEnded
+            } else if let Some(event) = tree::optional_str(transition, "on_event", &path)? {
+                StageTrigger::Event(event.to_string())
+            } else if condition.is_some() {
+                StageTrigger::Condition
+            } else {
+                return Err(DialogueLoadError::expected(
+                    path,
+                    "one of after, on_complete, on_event or if",
+                ));
+            };
+            Ok(StageTransition {
+                to: to.to_string(),
+                trigger,
+                condition,
+            })
+        })
+        .collect()
+}
+
+// Sent by game code to fire `on_event` transitions and barks, either for everyone or a single entity
+pub struct StageEvent {
+    pub name: std::string::String,
+    pub target: Option<Entity>,
+}
+
+pub struct StageChanged {
+    pub speaker: Entity,
+    pub from: std::string::String,
+    pub to: std::string::String,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn progress_stages(
+    time: Res<Time>,
+    app_state: Res<State<crate::AppState>>,
+    dialogue_tree: Res<tree::DialogueTree>,
+    dialogue_assets: Res<Assets<DialogueAsset>>,
+    vars: Res<DialogueVariables>,
+    mut stage_evr: EventReader<StageEvent>,
+    mut changed_evw: EventWriter<StageChanged>,
+    mut sources: Query<(Entity, &mut DialogueSource)>,
+    // (speaker, event name) of events which arrived while the speaker was being talked to
+    mut held_events: Local<Vec<(Entity, std::string::String)>>,
+) {
+    // the conversation isn't over until the dialogue box has closed
+    let in_dialogue = matches!(
+        app_state.current(),
+        crate::AppState::Dialogue | crate::AppState::ClosingDialogue
+    );
+    let stage_events: Vec<&StageEvent> = stage_evr.iter().collect();
+    for (entity, mut source) in sources.iter_mut() {
+        let aimed_at = |e: &StageEvent| e.target.map_or(true, |target| target == entity);
+        if in_dialogue {
+            // leave whoever we're talking to alone until the conversation is over, keeping
+            // their events for then
+            if dialogue_tree.speaker == Some(entity) {
+                held_events.extend(
+                    stage_events
+                        .iter()
+                        .filter(|e| aimed_at(e))
+                        .map(|e| (entity, e.name.clone())),
+                );
+                continue;
+            }
+        } else {
+            source.stage_time += time.delta_seconds();
+        }
+        let held: Vec<std::string::String> = if in_dialogue {
+            Vec::new()
+        } else {
+            let (held, rest) = held_events
+                .drain(..)
+                .partition(|(speaker, _)| *speaker == entity);
+            *held_events = rest;
+            held.into_iter().map(|(_, name)| name).collect()
+        };
+
+        let transitions = match dialogue_assets
+            .get(&source.handle)
+            .and_then(|asset| asset.stages.get(&source.stage))
+        {
+            Some(graph) => &graph.transitions,
+            None => continue,
+        };
+        let next = transitions.iter().find(|transition| {
+            let triggered = match &transition.trigger {
+                StageTrigger::Elapsed(seconds) => source.stage_time >= *seconds,
+                StageTrigger::Condition => true,
+                StageTrigger::ConversationEnded => source.completed,
+                StageTrigger::Event(name) => {
+                    stage_events
+                        .iter()
+                        .filter(|e| aimed_at(e))
+                        .any(|e| e.name == *name)
+                        || held.contains(name)
+                }
+            };
+            triggered && transition.condition.iter().all(|c| c.is_true(&vars))
+        });
+
+        if let Some(next) = next {
+            let to = next.to.clone();
+            changed_evw.send(StageChanged {
+                speaker: entity,
+                from: source.stage.clone(),
+                to: to.clone(),
+            });
+            source.set_stage(&to);
+        }
     }
 }
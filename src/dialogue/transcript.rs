@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+
+use super::asset::DialogueAsset;
+use super::events::{DialogueStarted, ResponseChosen};
+use super::tree::DialogueTree;
+use crate::ui;
+
+// how the player's responses are labelled in the backlog
+const PLAYER_NAME: &str = "You";
+const PLAYER_NAME_COLOR: Color = Color::rgb(0.6, 0.6, 0.6);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TranscriptSpeaker {
+    // a line of a node, with the name of its character if it has one
+    Npc(Option<std::string::String>),
+    // a response the player chose
+    Player,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranscriptEntry {
+    // who the conversation was with
+    pub npc: Entity,
+    pub speaker: TranscriptSpeaker,
+    pub node_id: std::string::String,
+    // without markup
+    pub text: std::string::String,
+    // seconds since startup
+    pub time: f64,
+}
+
+// Resource recording everything said in every conversation, oldest first
+#[derive(Default)]
+pub struct DialogueTranscript {
+    entries: Vec<TranscriptEntry>,
+}
+
+impl DialogueTranscript {
+    pub fn record(&mut self, entry: TranscriptEntry) {
+        self.entries.push(entry);
+    }
+}
+
+// Queries for game code, e.g. a quest journal
+impl DialogueTranscript {
+    // Everything said to or by one NPC, e.g. for a quest journal
+    pub fn for_npc(&self, npc: Entity) -> impl Iterator<Item = &TranscriptEntry> {
+        self.entries.iter().filter(move |entry| entry.npc == npc)
+    }
+
+    // Whether a node was ever shown while talking to the NPC, responses leading to one are dimmed
+    pub fn has_seen(&self, npc: Entity, node_id: &str) -> bool {
+        self.for_npc(npc).any(|entry| {
+            entry.node_id == node_id && matches!(entry.speaker, TranscriptSpeaker::Npc(_))
+        })
+    }
+}
+
+// Records chosen responses and each line as it's first shown, and passes them on to the
+// backlog panel
+#[allow(clippy::too_many_arguments)]
+pub fn record_transcript(
+    time: Res<Time>,
+    dialogue_tree: Res<DialogueTree>,
+    dialogue_assets: Res<Assets<DialogueAsset>>,
+    mut started_evr: EventReader<DialogueStarted>,
+    mut response_evr: EventReader<ResponseChosen>,
+    mut transcript: ResMut<DialogueTranscript>,
+    mut backlog_evw: EventWriter<ui::AppendBacklogEvent>,
+    // node and line last recorded, so reloads and other changes to the tree aren't repeated
+    mut last_line: Local<Option<(std::string::String, usize)>>,
+) {
+    let now = time.seconds_since_startup();
+    if started_evr.iter().count() > 0 {
+        *last_line = None;
+    }
+    for e in response_evr.iter() {
+        backlog_evw.send(ui::AppendBacklogEvent(ui::BacklogLineData {
+            speaker: Some(ui::SpeakerNameData {
+                name: PLAYER_NAME.to_string(),
+                color: PLAYER_NAME_COLOR,
+            }),
+            text: e.text.clone(),
+        }));
+        transcript.record(TranscriptEntry {
+            npc: e.speaker,
+            speaker: TranscriptSpeaker::Player,
+            node_id: e.node_id.clone(),
+            text: e.text.clone(),
+            time: now,
+        });
+        // the response may lead straight back to the line being shown
+        *last_line = None;
+    }
+
+    if !dialogue_tree.is_changed() {
+        return;
+    }
+    let (npc, line) = match (dialogue_tree.speaker, dialogue_tree.current_line()) {
+        (Some(npc), Some(line)) => (npc, ui::strip_markup(line)),
+        _ => return,
+    };
+    let position = (dialogue_tree.current.clone(), dialogue_tree.line);
+    if last_line.as_ref() == Some(&position) {
+        return;
+    }
+    *last_line = Some(position);
+
+    let character = dialogue_tree.current_character(&dialogue_assets);
+    backlog_evw.send(ui::AppendBacklogEvent(ui::BacklogLineData {
+        speaker: character.map(|character| ui::SpeakerNameData {
+            name: character.name.clone(),
+            color: character.color,
+        }),
+        text: line.clone(),
+    }));
+    transcript.record(TranscriptEntry {
+        npc,
+        speaker: TranscriptSpeaker::Npc(character.map(|character| character.name.clone())),
+        node_id: dialogue_tree.current.clone(),
+        text: line,
+        time: now,
+    });
+}
+
+#[test]
+pub fn test_transcript_queries() {
+    let cube = Entity::from_raw(1);
+    let sphere = Entity::from_raw(2);
+    let mut transcript = DialogueTranscript::default();
+    for (npc, speaker, node_id) in [
+        (cube, TranscriptSpeaker::Npc(Some("Cube".into())), "hi"),
+        (cube, TranscriptSpeaker::Player, "secret"),
+        (sphere, TranscriptSpeaker::Npc(None), "secret"),
+    ] {
+        transcript.record(TranscriptEntry {
+            npc,
+            speaker,
+            node_id: node_id.to_string(),
+            text: "...".to_string(),
+            time: 0.0,
+        });
+    }
+    assert_eq!(transcript.for_npc(cube).count(), 2);
+    assert_eq!(transcript.for_npc(sphere).count(), 1);
+    assert!(transcript.has_seen(cube, "hi"));
+    // choosing a response from a node isn't the same as being shown it
+    assert!(!transcript.has_seen(cube, "secret"));
+    assert!(transcript.has_seen(sphere, "secret"));
+}
@@ -1,101 +1,889 @@
 extern crate yaml_rust;
 
-use std::fs::{self};
+use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::*;
-use yaml_rust::{yaml, YamlLoader};
+use yaml_rust::yaml;
+
+use super::asset::DialogueAsset;
+use super::barks::{self, Bark};
+use super::characters::Character;
+use super::commands::{self, DialogueAction};
+use super::error::{DialogueLoadError, DialogueLoadErrorKind};
+use super::expr::Expr;
+use super::source::DialogueSource;
+use super::stages::{self, StageTransition};
+use super::variables::{DialogueValue, DialogueVariables, VariableEffect};
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct DialogueNode {
+pub struct DialogueLine {
     pub text: std::string::String,
+    // expression to show for just this line, e.g. `cube/angry`
+    pub portrait: Option<std::string::String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogueNode {
+    // unique within a stage, either written in the yaml or derived from the node's yaml path
+    pub id: std::string::String,
+    // for reporting problems found after loading
+    pub path: std::string::String,
+    // id of the character saying the line, None for the one being talked to
+    pub speaker: Option<std::string::String>,
+    // shown one after another, the responses only appear with the last line
+    pub lines: Vec<DialogueLine>,
+    // portrait for every line of the node, overriding the speaker's own
+    pub portrait: Option<std::string::String>,
+    // empty for nodes which end the conversation after their last line
     pub responses: Vec<ResponseNode>,
+    // responses leading here are hidden unless this holds
+    pub condition: Option<Expr>,
+    // run whenever the node is entered
+    pub actions: Vec<DialogueAction>,
+    // the player can't leave the conversation while it's shown
+    pub locked: bool,
+    // name of the camera shot framing the speaker, e.g. `closeup`
+    pub camera: Option<std::string::String>,
 }
 
-impl Drop for DialogueNode {
-    fn drop(&mut self) {
-        println!("Dropped: {}", self.text);
+impl DialogueNode {
+    // The line's own portrait, then the node's, then the speaking character's
+    pub fn portrait<'a>(
+        &'a self,
+        line: usize,
+        character: Option<&'a Character>,
+    ) -> Option<&'a str> {
+        self.lines
+            .get(line)
+            .and_then(|line| line.portrait.as_deref())
+            .or_else(|| self.portrait.as_deref())
+            .or_else(|| character?.portrait.as_deref())
+    }
+
+    // The node's own camera shot, then the speaking character's
+    pub fn camera<'a>(&'a self, character: Option<&'a Character>) -> Option<&'a str> {
+        self.camera
+            .as_deref()
+            .or_else(|| character?.camera.as_deref())
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ResponseNode {
     pub text: std::string::String,
-    pub dialogue_node: Option<DialogueNode>,
-}
-
-pub fn generate_dialogue_from_yaml(
-    stage_str: &std::string::String,
-    yaml_path: &str,
-) -> DialogueNode {
-    let docs = YamlLoader::load_from_str(&fs::read_to_string(yaml_path).unwrap()).unwrap();
-    let doc = &docs[0];
-    parse_dialogue_yaml(&doc[stage_str.as_str()]["dialogue"])
-}
-
-pub fn parse_dialogue_yaml(yaml: &yaml::Yaml) -> DialogueNode {
-    let responses = yaml["responses"]
-        .as_vec()
-        .unwrap()
-        .iter()
-        .map(|response_yaml| ResponseNode {
-            text: response_yaml["text"].as_str().unwrap().to_string(),
-            dialogue_node: if response_yaml["dialogue"].is_null() {
-                None
-            } else {
-                Some(parse_dialogue_yaml(&response_yaml["dialogue"]))
-            },
+    // id of the node this response leads to, None ends the conversation
+    pub next: Option<std::string::String>,
+    // set for responses which can only be chosen once
+    pub visited_id: Option<std::string::String>,
+    pub condition: Option<Expr>,
+    // applied to the variable store when the response is chosen
+    pub effects: Vec<VariableEffect>,
+    // run when the response is chosen
+    pub actions: Vec<DialogueAction>,
+}
+
+impl ResponseNode {
+    pub fn is_used_up(&self, visited: &HashSet<std::string::String>) -> bool {
+        self.visited_id
+            .as_ref()
+            .map_or(false, |id| visited.contains(id))
+    }
+}
+
+// All the dialogue nodes of a stage, addressed by id. Responses may point at any node,
+// including ones further up the conversation, so cycles are allowed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DialogueGraph {
+    pub start: std::string::String,
+    pub nodes: HashMap<std::string::String, DialogueNode>,
+    // node shown when the player leaves the conversation, before it ends
+    pub goodbye: Option<std::string::String>,
+    // ways out of this stage, checked in order
+    pub transitions: Vec<StageTransition>,
+    // said in passing while out of dialogue
+    pub barks: Vec<Bark>,
+}
+
+impl DialogueGraph {
+    pub fn node(&self, id: &str) -> Option<&DialogueNode> {
+        self.nodes.get(id)
+    }
+
+    // Every action of every node and response in the stage
+    pub fn actions(&self) -> impl Iterator<Item = &DialogueAction> {
+        self.nodes.values().flat_map(|node| {
+            node.actions
+                .iter()
+                .chain(node.responses.iter().flat_map(|r| r.actions.iter()))
         })
-        .collect();
-    DialogueNode {
-        text: yaml["text"].as_str().unwrap().into(),
-        responses,
     }
+
+    // A response is dead once it has been used up, or when everything that could be said
+    // after it has been
+    pub fn response_is_dead(
+        &self,
+        response: &ResponseNode,
+        visited: &HashSet<std::string::String>,
+    ) -> bool {
+        if response.is_used_up(visited) {
+            return true;
+        }
+        match response.next.as_ref().and_then(|next| self.node(next)) {
+            Some(node) => {
+                !node.responses.is_empty() && node.responses.iter().all(|r| r.is_used_up(visited))
+            }
+            None => false,
+        }
+    }
+
+    // Whether the player should be offered a response right now
+    pub fn response_is_shown(
+        &self,
+        response: &ResponseNode,
+        visited: &HashSet<std::string::String>,
+        vars: &DialogueVariables,
+    ) -> bool {
+        let target_condition = response
+            .next
+            .as_ref()
+            .and_then(|next| self.node(next))
+            .and_then(|node| node.condition.as_ref());
+        !self.response_is_dead(response, visited)
+            && response.condition.iter().all(|c| c.is_true(vars))
+            && target_condition.iter().all(|c| c.is_true(vars))
+    }
+
+    // True when a node has responses but none of them can be shown any more
+    pub fn node_is_exhausted(
+        &self,
+        node: &DialogueNode,
+        visited: &HashSet<std::string::String>,
+        vars: &DialogueVariables,
+    ) -> bool {
+        !node.responses.is_empty()
+            && !node
+                .responses
+                .iter()
+                .any(|r| self.response_is_shown(r, visited, vars))
+    }
+}
+
+pub fn parse_stage_yaml(
+    stage: &str,
+    yaml: &yaml::Yaml,
+    characters: &HashMap<std::string::String, Character>,
+) -> Result<DialogueGraph, DialogueLoadError> {
+    let mut parser = StageParser {
+        characters,
+        nodes: HashMap::new(),
+        gotos: Vec::new(),
+    };
+    parser
+        .parse_stage(stage, yaml)
+        .map_err(|e| e.in_stage(stage))
+}
+
+struct StageParser<'a> {
+    // characters of the file, which every speaker must be one of
+    characters: &'a HashMap<std::string::String, Character>,
+    nodes: HashMap<std::string::String, DialogueNode>,
+    // (yaml path, target id) of every goto, checked once all nodes are known
+    gotos: Vec<(std::string::String, std::string::String)>,
+}
+
+impl<'a> StageParser<'a> {
+    fn parse_stage(
+        &mut self,
+        stage: &str,
+        yaml: &yaml::Yaml,
+    ) -> Result<DialogueGraph, DialogueLoadError> {
+        let start = self.parse_dialogue(&yaml["dialogue"], format!("{}.dialogue", stage))?;
+
+        // nodes which are only reachable through goto
+        if let Some(extra_nodes) = yaml["nodes"].as_hash() {
+            for (id, node_yaml) in extra_nodes {
+                let path = format!("{}.nodes", stage);
+                let id = id
+                    .as_str()
+                    .ok_or_else(|| DialogueLoadError::expected(&path, "string node ids"))?;
+                let node_path = format!("{}.{}", path, id);
+                let node = self.parse_node(node_yaml, id.to_string(), &node_path)?;
+                self.insert_node(node, &node_path)?;
+            }
+        } else if !yaml["nodes"].is_badvalue() {
+            return Err(DialogueLoadError::expected(
+                format!("{}.nodes", stage),
+                "a map of node ids to nodes",
+            ));
+        }
+
+        // the node id to leave through, e.g. a node under `nodes` which says farewell
+        let goodbye = optional_str(yaml, "goodbye", stage)?.map(|g| g.to_string());
+        if let Some(goodbye) = &goodbye {
+            self.gotos
+                .push((format!("{}.goodbye", stage), goodbye.clone()));
+        }
+
+        if let Some((path, target)) = self
+            .gotos
+            .iter()
+            .find(|(_, target)| !self.nodes.contains_key(target))
+        {
+            return Err(DialogueLoadError::new(
+                path.as_str(),
+                DialogueLoadErrorKind::UnknownNode(target.clone()),
+            ));
+        }
+
+        Ok(DialogueGraph {
+            start,
+            nodes: std::mem::take(&mut self.nodes),
+            goodbye,
+            transitions: stages::parse_transitions(
+                &yaml["transitions"],
+                &format!("{}.transitions", stage),
+            )?,
+            barks: barks::parse_barks(
+                &yaml["barks"],
+                &format!("{}.barks", stage),
+                self.characters,
+            )?,
+        })
+    }
+
+    // Parses the node at `path` and all of its inline children, returning its id
+    fn parse_dialogue(
+        &mut self,
+        yaml: &yaml::Yaml,
+        path: std::string::String,
+    ) -> Result<std::string::String, DialogueLoadError> {
+        if yaml.as_hash().is_none() {
+            return Err(DialogueLoadError::expected(path, "a dialogue node"));
+        }
+        let id = match &yaml["id"] {
+            yaml::Yaml::BadValue => path.clone(),
+            id => id
+                .as_str()
+                .ok_or_else(|| DialogueLoadError::expected(format!("{}.id", path), "a string"))?
+                .to_string(),
+        };
+        let node = self.parse_node(yaml, id.clone(), &path)?;
+        self.insert_node(node, &path)?;
+        Ok(id)
+    }
+
+    fn parse_node(
+        &mut self,
+        yaml: &yaml::Yaml,
+        id: std::string::String,
+        path: &str,
+    ) -> Result<DialogueNode, DialogueLoadError> {
+        let lines = parse_lines(yaml, path)?;
+        let responses = match &yaml["responses"] {
+            yaml::Yaml::BadValue => Vec::new(),
+            yaml::Yaml::Array(responses) => responses
+                .iter()
+                .enumerate()
+                .map(|(i, response_yaml)| {
+                    self.parse_response(response_yaml, &format!("{}.responses[{}]", path, i))
+                })
+                .collect::<Result<_, _>>()?,
+            _ => {
+                return Err(DialogueLoadError::expected(
+                    format!("{}.responses", path),
+                    "a list of responses",
+                ))
+            }
+        };
+        let speaker = optional_str(yaml, "speaker", path)?;
+        if let Some(speaker) = speaker {
+            if !self.characters.contains_key(speaker) {
+                return Err(DialogueLoadError::new(
+                    format!("{}.speaker", path),
+                    DialogueLoadErrorKind::UnknownCharacter(speaker.to_string()),
+                ));
+            }
+        }
+        Ok(DialogueNode {
+            id,
+            path: path.to_string(),
+            speaker: speaker.map(|s| s.to_string()),
+            lines,
+            portrait: optional_str(yaml, "portrait", path)?.map(|p| p.to_string()),
+            responses,
+            condition: optional_expr(yaml, "if", path)?,
+            actions: commands::parse_actions(&yaml["actions"], path)?,
+            locked: optional_bool(yaml, "locked", path)?.unwrap_or(false),
+            camera: optional_str(yaml, "camera", path)?.map(|c| c.to_string()),
+        })
+    }
+
+    fn parse_response(
+        &mut self,
+        yaml: &yaml::Yaml,
+        path: &str,
+    ) -> Result<ResponseNode, DialogueLoadError> {
+        let text = yaml["text"]
+            .as_str()
+            .ok_or_else(|| DialogueLoadError::expected(format!("{}.text", path), "a string"))?;
+        let next = match (&yaml["goto"], &yaml["dialogue"]) {
+            (yaml::Yaml::BadValue, yaml::Yaml::BadValue | yaml::Yaml::Null) => None,
+            (yaml::Yaml::BadValue, dialogue) => {
+                Some(self.parse_dialogue(dialogue, format!("{}.dialogue", path))?)
+            }
+            (goto, yaml::Yaml::BadValue | yaml::Yaml::Null) => {
+                let goto_path = format!("{}.goto", path);
+                let target = goto
+                    .as_str()
+                    .ok_or_else(|| DialogueLoadError::expected(&goto_path, "a node id"))?;
+                self.gotos.push((goto_path, target.to_string()));
+                Some(target.to_string())
+            }
+            _ => {
+                return Err(DialogueLoadError::expected(
+                    path,
+                    "either goto or dialogue, not both",
+                ))
+            }
+        };
+        // responses with a visited_id, or marked once, can only be chosen a single time
+        let visited_id = match (
+            optional_str(yaml, "visited_id", path)?,
+            optional_bool(yaml, "once", path)?,
+        ) {
+            (Some(id), _) => Some(id.to_string()),
+            (None, Some(true)) => Some(path.to_string()),
+            (None, _) => None,
+        };
+        Ok(ResponseNode {
+            text: text.to_string(),
+            next,
+            visited_id,
+            condition: optional_expr(yaml, "if", path)?,
+            effects: parse_effects(yaml, path)?,
+            actions: commands::parse_actions(&yaml["actions"], path)?,
+        })
+    }
+
+    fn insert_node(&mut self, node: DialogueNode, path: &str) -> Result<(), DialogueLoadError> {
+        if self.nodes.contains_key(&node.id) {
+            return Err(DialogueLoadError::new(
+                path,
+                DialogueLoadErrorKind::DuplicateNode(node.id),
+            ));
+        }
+        self.nodes.insert(node.id.clone(), node);
+        Ok(())
+    }
+}
+
+pub fn optional_str<'a>(
+    yaml: &'a yaml::Yaml,
+    key: &str,
+    path: &str,
+) -> Result<Option<&'a str>, DialogueLoadError> {
+    match &yaml[key] {
+        yaml::Yaml::BadValue => Ok(None),
+        value => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| DialogueLoadError::expected(format!("{}.{}", path, key), "a string")),
+    }
+}
+
+pub fn optional_bool(
+    yaml: &yaml::Yaml,
+    key: &str,
+    path: &str,
+) -> Result<Option<bool>, DialogueLoadError> {
+    match &yaml[key] {
+        yaml::Yaml::BadValue => Ok(None),
+        value => value.as_bool().map(Some).ok_or_else(|| {
+            DialogueLoadError::expected(format!("{}.{}", path, key), "true or false")
+        }),
+    }
+}
+
+pub fn optional_number(
+    yaml: &yaml::Yaml,
+    key: &str,
+    path: &str,
+) -> Result<Option<f32>, DialogueLoadError> {
+    match &yaml[key] {
+        yaml::Yaml::BadValue => Ok(None),
+        yaml::Yaml::Integer(i) => Ok(Some(*i as f32)),
+        value => value
+            .as_f64()
+            .map(|f| Some(f as f32))
+            .ok_or_else(|| DialogueLoadError::expected(format!("{}.{}", path, key), "a number")),
+    }
+}
+
+pub fn optional_expr(
+    yaml: &yaml::Yaml,
+    key: &str,
+    path: &str,
+) -> Result<Option<Expr>, DialogueLoadError> {
+    optional_str(yaml, key, path)?
+        .map(|source| {
+            Expr::parse(source).map_err(|e| {
+                DialogueLoadError::new(
+                    format!("{}.{}", path, key),
+                    DialogueLoadErrorKind::Expression(e),
+                )
+            })
+        })
+        .transpose()
+}
+
+// A node says either a single `text`, or a list of `lines`. Each line is a string, or a map
+// with `text` and a `portrait`.
+fn parse_lines(yaml: &yaml::Yaml, path: &str) -> Result<Vec<DialogueLine>, DialogueLoadError> {
+    match (&yaml["text"], &yaml["lines"]) {
+        (text, yaml::Yaml::BadValue) => Ok(vec![DialogueLine {
+            text: text
+                .as_str()
+                .ok_or_else(|| DialogueLoadError::expected(format!("{}.text", path), "a string"))?
+                .to_string(),
+            portrait: None,
+        }]),
+        (yaml::Yaml::BadValue, yaml::Yaml::Array(lines)) if !lines.is_empty() => lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let path = format!("{}.lines[{}]", path, i);
+                match line {
+                    yaml::Yaml::String(text) => Ok(DialogueLine {
+                        text: text.clone(),
+                        portrait: None,
+                    }),
+                    yaml::Yaml::Hash(_) => Ok(DialogueLine {
+                        text: line["text"]
+                            .as_str()
+                            .ok_or_else(|| {
+                                DialogueLoadError::expected(format!("{}.text", path), "a string")
+                            })?
+                            .to_string(),
+                        portrait: optional_str(line, "portrait", &path)?.map(|p| p.to_string()),
+                    }),
+                    _ => Err(DialogueLoadError::expected(
+                        path,
+                        "a string, or a map with text and portrait",
+                    )),
+                }
+            })
+            .collect(),
+        (yaml::Yaml::BadValue, _) => Err(DialogueLoadError::expected(
+            format!("{}.lines", path),
+            "a non-empty list of lines",
+        )),
+        _ => Err(DialogueLoadError::expected(
+            path,
+            "either text or lines, not both",
+        )),
+    }
+}
+
+// `set: { name: value }` assigns variables, `add: { name: number }` adds to them
+fn parse_effects(yaml: &yaml::Yaml, path: &str) -> Result<Vec<VariableEffect>, DialogueLoadError> {
+    let mut effects = Vec::new();
+    for key in ["set", "add"] {
+        let entries = match &yaml[key] {
+            yaml::Yaml::BadValue => continue,
+            yaml::Yaml::Hash(entries) => entries,
+            _ => {
+                return Err(DialogueLoadError::expected(
+                    format!("{}.{}", path, key),
+                    "a map of variable names to values",
+                ))
+            }
+        };
+        for (name, value) in entries {
+            let entry_path = format!("{}.{}", path, key);
+            let name = name
+                .as_str()
+                .ok_or_else(|| DialogueLoadError::expected(&entry_path, "variable names"))?;
+            let value_path = format!("{}.{}", entry_path, name);
+            let value = DialogueValue::from_yaml(value).ok_or_else(|| {
+                DialogueLoadError::expected(&value_path, "a bool, number or string")
+            })?;
+            effects.push(if key == "set" {
+                VariableEffect::Set(name.to_string(), value)
+            } else if value.as_f64().is_some() {
+                VariableEffect::Add(name.to_string(), value)
+            } else {
+                return Err(DialogueLoadError::expected(value_path, "a number"));
+            });
+        }
+    }
+    Ok(effects)
 }
 
 #[test]
-pub fn test_generate_dialogue_from_yaml() {
-    let node = generate_dialogue_from_yaml(
-        &"default".to_string(),
-        "./assets/dialogue/test_dialogue.yaml",
-    );
-    assert_eq!(node.text, "Hi");
-    assert_eq!(node.responses[0].text, "Hello");
+pub fn test_dialogue_load_errors() {
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  dialogue:
+    text: Hi
+    responses:
+      - text: Hello
+        goto: nowhere
+      - dialogue: ~
+",
+    )
+    .unwrap();
+    let err = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap_err();
+    assert_eq!(err.stage.as_deref(), Some("default"));
+    assert_eq!(err.path, "default.dialogue.responses[1].text");
+    assert_eq!(err.kind, DialogueLoadErrorKind::Expected("a string"));
+
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  dialogue:
+    text: Hi
+    responses:
+      - text: Hello
+        goto: nowhere
+",
+    )
+    .unwrap();
+    let err = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap_err();
+    assert_eq!(err.path, "default.dialogue.responses[0].goto");
     assert_eq!(
-        node.responses[0].dialogue_node.as_ref().unwrap().text,
-        "I can't talk now."
+        err.kind,
+        DialogueLoadErrorKind::UnknownNode("nowhere".to_string())
     );
+
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  dialogue:
+    id: hello
+    text: Hi
+  nodes:
+    hello:
+      text: Hi again
+",
+    )
+    .unwrap();
+    let err = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap_err();
+    assert_eq!(err.path, "default.nodes.hello");
     assert_eq!(
-        node.responses[0].dialogue_node.as_ref().unwrap().responses[0].text,
-        "Oh.."
+        err.kind,
+        DialogueLoadErrorKind::DuplicateNode("hello".to_string())
     );
+
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  goodbye: farewell
+  dialogue:
+    text: Hi
+    locked: true
+",
+    )
+    .unwrap();
+    let err = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap_err();
+    assert_eq!(err.path, "default.goodbye");
     assert_eq!(
-        node.responses[0].dialogue_node.as_ref().unwrap().responses[0].dialogue_node,
-        None
+        err.kind,
+        DialogueLoadErrorKind::UnknownNode("farewell".to_string())
     );
-    assert_eq!(node.responses[1].text, "Goodbye");
+
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  goodbye: farewell
+  dialogue:
+    text: Hi
+    locked: true
+  nodes:
+    farewell:
+      text: Bye then.
+",
+    )
+    .unwrap();
+    let graph = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap();
+    assert_eq!(graph.goodbye.as_deref(), Some("farewell"));
+    assert!(graph.nodes[&graph.start].locked);
+    assert!(!graph.nodes["farewell"].locked);
 }
 
+#[derive(Default)]
 pub struct DialogueTree {
-    pub root: DialogueNode,
+    // entity being talked to
+    pub speaker: Option<Entity>,
+    pub file: std::string::String,
+    pub handle: Handle<DialogueAsset>,
+    // stage the graph was taken from
+    pub stage: std::string::String,
+    pub graph: DialogueGraph,
+    // id of the node currently being shown
+    pub current: std::string::String,
+    // index of the current node's line being shown
+    pub line: usize,
+    // the player asked to leave and is being shown the stage's goodbye node
+    pub leaving: bool,
 }
 
 impl DialogueTree {
-    pub fn load_stage(&mut self, stage: &std::string::String) {
-        self.root = generate_dialogue_from_yaml(stage, "./assets/dialogue/cube_dialogue.yaml");
+    fn stage_graph(
+        &self,
+        stage: &str,
+        assets: &Assets<DialogueAsset>,
+    ) -> Result<DialogueGraph, DialogueLoadError> {
+        let asset = assets.get(&self.handle).ok_or_else(|| {
+            DialogueLoadError::new("", DialogueLoadErrorKind::NotLoaded).in_file(&self.file)
+        })?;
+        asset.stages.get(stage).cloned().ok_or_else(|| {
+            DialogueLoadError::new(stage, DialogueLoadErrorKind::UnknownStage)
+                .in_file(&self.file)
+                .in_stage(stage)
+        })
+    }
+
+    // Starts a conversation with `speaker` from its source's current stage
+    pub fn start(
+        &mut self,
+        speaker: Entity,
+        source: &DialogueSource,
+        assets: &Assets<DialogueAsset>,
+    ) -> Result<(), DialogueLoadError> {
+        self.speaker = Some(speaker);
+        self.leaving = false;
+        self.file = source.file.clone();
+        self.handle = source.handle.clone();
+        self.load_stage(&source.stage, assets)
+    }
+
+    // Replaces the conversation with the start of the given stage. On failure the current
+    // conversation is left as it was.
+    pub fn load_stage(
+        &mut self,
+        stage: &str,
+        assets: &Assets<DialogueAsset>,
+    ) -> Result<(), DialogueLoadError> {
+        self.graph = self.stage_graph(stage, assets)?;
+        self.stage = stage.to_string();
+        self.enter(self.graph.start.clone());
+        Ok(())
     }
 
-    fn new() -> Self {
-        Self {
-            root: generate_dialogue_from_yaml(
-                &"default".to_string(),
-                "./assets/dialogue/cube_dialogue.yaml",
-            ),
+    // Picks up changes to the asset, staying on the current node if it still exists
+    pub fn reload(&mut self, assets: &Assets<DialogueAsset>) -> Result<(), DialogueLoadError> {
+        self.graph = self.stage_graph(&self.stage, assets)?;
+        match self.graph.node(&self.current) {
+            Some(node) => self.line = self.line.min(node.lines.len() - 1),
+            None => self.enter(self.graph.start.clone()),
         }
+        Ok(())
+    }
+
+    // Moves to the first line of another node
+    pub fn enter(&mut self, id: std::string::String) {
+        self.current = id;
+        self.line = 0;
+    }
+
+    pub fn current_node(&self) -> Option<&DialogueNode> {
+        self.graph.node(&self.current)
+    }
+
+    pub fn current_line(&self) -> Option<&str> {
+        self.current_node()
+            .and_then(|node| node.lines.get(self.line))
+            .map(|line| line.text.as_str())
+    }
+
+    pub fn current_portrait<'a>(&'a self, assets: &'a Assets<DialogueAsset>) -> Option<&'a str> {
+        self.current_node()?
+            .portrait(self.line, self.current_character(assets))
+    }
+
+    pub fn current_camera<'a>(&'a self, assets: &'a Assets<DialogueAsset>) -> Option<&'a str> {
+        self.current_node()?.camera(self.current_character(assets))
+    }
+
+    // The character saying the current node's lines, if it names one
+    pub fn current_character<'a>(
+        &self,
+        assets: &'a Assets<DialogueAsset>,
+    ) -> Option<&'a Character> {
+        let speaker = self.current_node()?.speaker.as_ref()?;
+        assets.get(&self.handle)?.characters.get(speaker)
+    }
+
+    // Responses are only offered once every line of the node has been shown
+    pub fn on_last_line(&self) -> bool {
+        self.current_node()
+            .map_or(true, |node| self.line + 1 >= node.lines.len())
+    }
+
+    pub fn current_is_exhausted(
+        &self,
+        visited: &HashSet<std::string::String>,
+        vars: &DialogueVariables,
+    ) -> bool {
+        self.current_node().map_or(false, |node| {
+            self.graph.node_is_exhausted(node, visited, vars)
+        })
     }
 }
 
-impl FromWorld for DialogueTree {
-    fn from_world(_: &mut World) -> Self {
-        DialogueTree::new()
+#[test]
+pub fn test_once_only_responses() {
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  dialogue:
+    id: root
+    text: Hi
+    responses:
+      - text: Who are you?
+        once: true
+        goto: root
+      - text: Tell me a secret.
+        dialogue:
+          text: Just one.
+          responses:
+            - text: Go on.
+              visited_id: secret
+              goto: root
+      - text: Bye.
+        dialogue: ~
+",
+    )
+    .unwrap();
+    let graph = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap();
+    let root = graph.node("root").unwrap();
+    assert_eq!(
+        root.responses[0].visited_id.as_deref(),
+        Some("default.dialogue.responses[0]")
+    );
+    assert_eq!(root.responses[2].visited_id, None);
+
+    let mut visited = HashSet::new();
+    assert!(!graph.response_is_dead(&root.responses[0], &visited));
+    visited.insert("default.dialogue.responses[0]".to_string());
+    assert!(graph.response_is_dead(&root.responses[0], &visited));
+
+    // everything after the secret response has been said
+    visited.insert("secret".to_string());
+    assert!(graph.response_is_dead(&root.responses[1], &visited));
+    // leaving is always possible
+    assert!(!graph.response_is_dead(&root.responses[2], &visited));
+    assert!(!graph.node_is_exhausted(root, &visited, &DialogueVariables::default()));
+}
+
+#[test]
+pub fn test_conditional_responses() {
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  dialogue:
+    id: root
+    text: Hi
+    responses:
+      - text: Nice to meet you.
+        if: '!met_cube'
+        set: { met_cube: true }
+        add: { trust: 1 }
+        goto: root
+      - text: Tell me a secret.
+        goto: secret
+  nodes:
+    secret:
+      if: met_cube && trust >= 2
+      text: Fine.
+      responses: []
+",
+    )
+    .unwrap();
+    let graph = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap();
+    let root = graph.node("root").unwrap();
+    let visited = HashSet::new();
+    let mut vars = DialogueVariables::default();
+    assert!(graph.response_is_shown(&root.responses[0], &visited, &vars));
+    assert!(!graph.response_is_shown(&root.responses[1], &visited, &vars));
+
+    for effect in root.responses[0].effects.iter() {
+        vars.apply(effect);
     }
+    assert_eq!(vars.get("met_cube"), Some(&DialogueValue::Bool(true)));
+    assert!(!graph.response_is_shown(&root.responses[0], &visited, &vars));
+    assert!(graph.node_is_exhausted(root, &visited, &vars));
+
+    vars.apply(&VariableEffect::Add("trust".into(), DialogueValue::Int(1)));
+    assert_eq!(vars.get("trust"), Some(&DialogueValue::Int(2)));
+    assert!(graph.response_is_shown(&root.responses[1], &visited, &vars));
+
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  dialogue:
+    text: Hi
+    responses:
+      - text: Hello
+        if: trust >=
+",
+    )
+    .unwrap();
+    let err = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap_err();
+    assert_eq!(err.path, "default.dialogue.responses[0].if");
+}
+
+#[test]
+pub fn test_multi_line_nodes() {
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  dialogue:
+    lines:
+      - Let me tell you a story.
+      - It's a short one.
+    responses:
+      - text: Go on.
+        dialogue:
+          text: The end.
+  nodes:
+    both:
+      text: Hi
+      lines: [Hi]
+",
+    )
+    .unwrap();
+    let err = parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap_err();
+    assert_eq!(err.path, "default.nodes.both");
+
+    let docs = yaml_rust::YamlLoader::load_from_str(
+        "
+default:
+  dialogue:
+    lines:
+      - Let me tell you a story.
+      - It's a short one.
+    responses:
+      - text: Go on.
+        dialogue:
+          text: The end.
+",
+    )
+    .unwrap();
+    let mut tree = DialogueTree {
+        graph: parse_stage_yaml("default", &docs[0]["default"], &HashMap::new()).unwrap(),
+        ..Default::default()
+    };
+    tree.enter(tree.graph.start.clone());
+    assert_eq!(tree.current_line(), Some("Let me tell you a story."));
+    assert!(!tree.on_last_line());
+    tree.line += 1;
+    assert_eq!(tree.current_line(), Some("It's a short one."));
+    assert!(tree.on_last_line());
+
+    tree.enter("default.dialogue.responses[0].dialogue".to_string());
+    assert_eq!(tree.current_line(), Some("The end."));
+    assert!(tree.on_last_line());
+    assert!(tree.current_node().unwrap().responses.is_empty());
 }
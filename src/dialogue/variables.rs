@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use yaml_rust::yaml;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DialogueValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(std::string::String),
+}
+
+impl DialogueValue {
+    pub fn from_yaml(yaml: &yaml::Yaml) -> Option<Self> {
+        match yaml {
+            yaml::Yaml::Boolean(b) => Some(Self::Bool(*b)),
+            yaml::Yaml::Integer(i) => Some(Self::Int(*i)),
+            yaml::Yaml::Real(_) => yaml.as_f64().map(Self::Float),
+            yaml::Yaml::String(s) => Some(Self::String(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::Bool(b) => *b,
+            Self::Int(i) => *i != 0,
+            Self::Float(f) => *f != 0.0,
+            Self::String(s) => !s.is_empty(),
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(i) => Some(*i as f64),
+            Self::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DialogueValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Float(x) => write!(f, "{}", x),
+            Self::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+// A change to the variable store made when a response is chosen
+#[derive(Clone, Debug, PartialEq)]
+pub enum VariableEffect {
+    Set(std::string::String, DialogueValue),
+    // adds to a number, treating unset variables as 0
+    Add(std::string::String, DialogueValue),
+}
+
+// Resource holding everything the dialogue knows about the game, set either by responses or
+// by game code
+#[derive(Default)]
+pub struct DialogueVariables {
+    values: HashMap<std::string::String, DialogueValue>,
+}
+
+impl DialogueVariables {
+    pub fn get(&self, name: &str) -> Option<&DialogueValue> {
+        self.values.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: DialogueValue) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn apply(&mut self, effect: &VariableEffect) {
+        match effect {
+            VariableEffect::Set(name, value) => self.set(name, value.clone()),
+            VariableEffect::Add(name, amount) => {
+                let sum = match (self.get(name), amount) {
+                    (None, amount) => amount.clone(),
+                    // values come from dialogue files, so a huge one mustn't overflow
+                    (Some(DialogueValue::Int(a)), DialogueValue::Int(b)) => {
+                        DialogueValue::Int(a.saturating_add(*b))
+                    }
+                    (Some(value), amount) => match (value.as_f64(), amount.as_f64()) {
+                        (Some(a), Some(b)) => DialogueValue::Float(a + b),
+                        // adding to something that isn't a number replaces it
+                        _ => amount.clone(),
+                    },
+                };
+                self.set(name, sum);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn test_adding_variables() {
+    let mut vars = DialogueVariables::default();
+    let add = |amount| VariableEffect::Add("gold".to_string(), DialogueValue::Int(amount));
+    vars.apply(&add(5));
+    vars.apply(&add(2));
+    assert_eq!(vars.get("gold"), Some(&DialogueValue::Int(7)));
+    vars.apply(&add(i64::MAX));
+    assert_eq!(vars.get("gold"), Some(&DialogueValue::Int(i64::MAX)));
+}
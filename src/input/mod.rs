@@ -1,5 +1,14 @@
 use crate::math;
-use bevy::{input::mouse::MouseMotion, prelude::*};
+use bevy::{
+    input::{
+        gamepad::Gamepads,
+        mouse::{MouseMotion, MouseWheel},
+    },
+    prelude::*,
+};
+
+// shown in the interaction prompt
+pub const INTERACT_KEY: KeyCode = KeyCode::E;
 
 const ROT_SPEED: f32 = 0.001;
 const PITCH_MIN: f32 = 0. * math::DEG_TO_RAD;
@@ -13,9 +22,48 @@ pub struct Inputs {
     pub rot_dir: Vec2,
 
     pub interact: bool,
+    // moves dialogue on to its next line
+    pub continue_dialogue: bool,
+    // walks away from the conversation
     pub exit_dialogue: bool,
+    // moving between and picking dialogue responses
+    pub response_up: bool,
+    pub response_down: bool,
+    pub response_confirm: bool,
+    // index of a response picked directly with the number keys
+    pub response_shortcut: Option<usize>,
+
+    pub toggle_backlog: bool,
+    // lines to scroll the backlog by, positive is back towards older lines
+    pub backlog_scroll: i32,
+}
+
+// Resource with the keys which can be rebound
+pub struct InputBindings {
+    pub leave_dialogue: KeyCode,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            // escape quits the game
+            leave_dialogue: KeyCode::Q,
+        }
+    }
 }
 
+const SHORTCUT_KEYS: [(KeyCode, KeyCode); 9] = [
+    (KeyCode::Key1, KeyCode::Numpad1),
+    (KeyCode::Key2, KeyCode::Numpad2),
+    (KeyCode::Key3, KeyCode::Numpad3),
+    (KeyCode::Key4, KeyCode::Numpad4),
+    (KeyCode::Key5, KeyCode::Numpad5),
+    (KeyCode::Key6, KeyCode::Numpad6),
+    (KeyCode::Key7, KeyCode::Numpad7),
+    (KeyCode::Key8, KeyCode::Numpad8),
+    (KeyCode::Key9, KeyCode::Numpad9),
+];
+
 fn wrap_rotation(n: f32, min: f32, max: f32) -> f32 {
     if n < min {
         max
@@ -26,10 +74,25 @@ fn wrap_rotation(n: f32, min: f32, max: f32) -> f32 {
     }
 }
 
+// Whether any connected gamepad just pressed the button
+fn gamepad_just_pressed(
+    gamepads: &Gamepads,
+    buttons: &Input<GamepadButton>,
+    button_type: GamepadButtonType,
+) -> bool {
+    gamepads
+        .iter()
+        .any(|gamepad| buttons.just_pressed(GamepadButton(*gamepad, button_type)))
+}
+
 pub fn update_inputs(
     mut inputs: ResMut<Inputs>,
+    bindings: Res<InputBindings>,
     keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
     mut motion_evr: EventReader<MouseMotion>,
+    mut wheel_evr: EventReader<MouseWheel>,
 ) {
     let mut wish_dir = Vec3::ZERO;
     if keys.pressed(KeyCode::W) {
@@ -61,8 +124,39 @@ pub fn update_inputs(
         // println!("rot: {}", inputs.rot_dir * math::RAD_TO_DEG);
     }
 
-    inputs.interact = keys.just_released(KeyCode::E);
-    inputs.exit_dialogue = keys.just_released(KeyCode::Space);
+    inputs.interact = keys.just_released(INTERACT_KEY);
+    let pad = |button_type| gamepad_just_pressed(&gamepads, &gamepad_buttons, button_type);
+    inputs.continue_dialogue = keys.just_released(KeyCode::Space) || pad(GamepadButtonType::South);
+    inputs.exit_dialogue =
+        keys.just_pressed(bindings.leave_dialogue) || pad(GamepadButtonType::East);
+    inputs.response_up = keys.just_pressed(KeyCode::Up)
+        || keys.just_pressed(KeyCode::W)
+        || pad(GamepadButtonType::DPadUp);
+    inputs.response_down = keys.just_pressed(KeyCode::Down)
+        || keys.just_pressed(KeyCode::S)
+        || pad(GamepadButtonType::DPadDown);
+    inputs.response_confirm = keys.just_pressed(KeyCode::Return)
+        || keys.just_pressed(KeyCode::NumpadEnter)
+        || pad(GamepadButtonType::South);
+    inputs.response_shortcut = SHORTCUT_KEYS
+        .iter()
+        .position(|(key, numpad)| keys.just_pressed(*key) || keys.just_pressed(*numpad));
+
+    inputs.toggle_backlog = keys.just_pressed(KeyCode::Tab) || pad(GamepadButtonType::North);
+    let wheel: f32 = wheel_evr.iter().map(|ev| ev.y).sum();
+    // not signum, which is 1 for no scrolling at all
+    inputs.backlog_scroll = if wheel > 0.0 {
+        1
+    } else if wheel < 0.0 {
+        -1
+    } else {
+        0
+    };
+    if keys.just_pressed(KeyCode::PageUp) {
+        inputs.backlog_scroll = 5;
+    } else if keys.just_pressed(KeyCode::PageDown) {
+        inputs.backlog_scroll = -5;
+    }
 }
 
 pub struct InputPlugin;
@@ -70,6 +164,7 @@ pub struct InputPlugin;
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Inputs::default())
+            .init_resource::<InputBindings>()
             .add_system(update_inputs.label("input"));
     }
 }
@@ -1,8 +1,110 @@
-use crate::{camera, input};
+use crate::{camera, dialogue, input, ui};
 use bevy::prelude::*;
 
+// Shape the camera's ray is tested against, centred on the entity. Boxes stay axis aligned
+// whatever the entity's rotation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InteractShape {
+    // half extents
+    Box(Vec3),
+    Sphere(f32),
+}
+
+impl InteractShape {
+    // Distance along the ray to where it first touches the shape, 0 from inside it
+    pub fn ray_distance(&self, origin: Vec3, dir: Vec3, center: Vec3) -> Option<f32> {
+        match *self {
+            InteractShape::Box(half_extents) => {
+                // slab test, dividing by 0 gives infinities which still compare correctly
+                let inv_dir = dir.recip();
+                let t1 = (center - half_extents - origin) * inv_dir;
+                let t2 = (center + half_extents - origin) * inv_dir;
+                let near = t1.min(t2).max_element();
+                let far = t1.max(t2).min_element();
+                if near > far || far < 0.0 {
+                    None
+                } else {
+                    Some(near.max(0.0))
+                }
+            }
+            InteractShape::Sphere(radius) => {
+                let to_center = center - origin;
+                let along = to_center.dot(dir);
+                let miss_sq = to_center.length_squared() - along * along;
+                if miss_sq > radius * radius {
+                    return None;
+                }
+                let half_chord = (radius * radius - miss_sq).sqrt();
+                if along + half_chord < 0.0 {
+                    None
+                } else {
+                    Some((along - half_chord).max(0.0))
+                }
+            }
+        }
+    }
+}
+
+// seconds an inspected entity's description stays up
+const INSPECT_SECONDS: f32 = 4.0;
+
+// What happens when the player interacts with something
+#[derive(Clone, Debug, PartialEq)]
+pub enum InteractionKind {
+    // start a conversation with the entity's DialogueSource
+    Talk,
+    // float a line describing it above it
+    Inspect(std::string::String),
+    // take the item with this id, removing the entity from the world
+    PickUp(std::string::String),
+    // send an InteractionEvent with this name for game code to handle, e.g. opening a door
+    Custom(std::string::String),
+}
+
+#[derive(Component)]
+pub struct Interactable {
+    pub shape: InteractShape,
+    pub kind: InteractionKind,
+}
+
+// Sent when an item is picked up, the entity it was on has been despawned
+pub struct ItemPickedUp {
+    pub item: std::string::String,
+}
+
+// Sent by InteractionKind::Custom interactables
+pub struct InteractionEvent {
+    pub name: std::string::String,
+    pub entity: Entity,
+}
+
+// Shown on screen while the entity is targeted, after the interact key, e.g. "Talk to Cube"
 #[derive(Component)]
-pub struct Interactable(pub Vec3);
+pub struct InteractionPrompt(pub std::string::String);
+
+// Tints the entity's materials while it's targeted
+#[derive(Component)]
+pub struct InteractionHighlight {
+    pub emissive: Color,
+}
+
+// Materials swapped out for tinted copies, to be put back once the entity isn't targeted
+#[derive(Component)]
+pub struct Highlighted {
+    originals: Vec<(Entity, Handle<StandardMaterial>)>,
+}
+
+// Resource for tweaking what can be interacted with
+pub struct InteractionSettings {
+    // furthest the camera can be from something to interact with it
+    pub max_distance: f32,
+}
+
+impl Default for InteractionSettings {
+    fn default() -> Self {
+        Self { max_distance: 3.0 }
+    }
+}
 
 pub struct Interaction {
     pub current_interaction: Option<Entity>,
@@ -16,39 +118,208 @@ impl Default for Interaction {
     }
 }
 
-fn point_in_aabb(a_center: Vec3, b_center: Vec3, b_bounds: Vec3) -> bool {
-    let min_bounds = b_center - b_bounds;
-    let max_bounds = b_center + b_bounds;
-    (a_center.x >= min_bounds.x && a_center.x <= max_bounds.x)
-        && (a_center.y >= min_bounds.y && a_center.y <= max_bounds.y)
-        && (a_center.z >= min_bounds.z && a_center.z <= max_bounds.z)
+// The closest of `targets` hit by the ray within `max_distance`
+fn nearest_hit(
+    origin: Vec3,
+    dir: Vec3,
+    max_distance: f32,
+    targets: impl Iterator<Item = (Entity, Vec3, InteractShape)>,
+) -> Option<Entity> {
+    targets
+        .filter_map(|(entity, center, shape)| {
+            shape
+                .ray_distance(origin, dir, center)
+                .map(|distance| (entity, distance))
+        })
+        .filter(|(_, distance)| *distance <= max_distance)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(entity, _)| entity)
 }
 
+// Targets whatever the camera is looking at
 pub fn check_interactable(
-    interactable_query: Query<(&Transform, &Interactable, Entity)>,
+    settings: Res<InteractionSettings>,
+    interactable_query: Query<(Entity, &GlobalTransform, &Interactable)>,
     cam_query: Query<&Transform, With<camera::MainCamera>>,
     mut interaction: ResMut<Interaction>,
 ) {
     let cam_trans = cam_query.single();
-    for (inter_trans, inter_bounds, entity) in interactable_query.iter() {
-        let cam_point = cam_trans.translation + (cam_trans.forward() * 2.0);
-        if point_in_aabb(cam_point, inter_trans.translation, inter_bounds.0) {
-            // interactable found
-            interaction.current_interaction = Some(entity);
-        } else {
-            interaction.current_interaction = None;
+    let target = nearest_hit(
+        cam_trans.translation,
+        cam_trans.forward(),
+        settings.max_distance,
+        interactable_query
+            .iter()
+            .map(|(entity, transform, interactable)| {
+                (entity, transform.translation, interactable.shape)
+            }),
+    );
+    if interaction.current_interaction != target {
+        interaction.current_interaction = target;
+    }
+}
+
+// Every entity under `root`, and `root` itself
+fn descendants(root: Entity, children_query: &Query<&Children>) -> Vec<Entity> {
+    let mut found = vec![root];
+    let mut i = 0;
+    while i < found.len() {
+        if let Ok(children) = children_query.get(found[i]) {
+            found.extend(children.iter().copied());
         }
+        i += 1;
     }
+    found
 }
 
+// Materials are usually shared with other entities, e.g. everything spawned from the same
+// model, so the target's meshes get their own tinted copies rather than changing the originals
+pub fn highlight_target(
+    mut commands: Commands,
+    interaction: Res<Interaction>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    highlights: Query<&InteractionHighlight, Without<Highlighted>>,
+    highlighted: Query<(Entity, &Highlighted)>,
+    children_query: Query<&Children>,
+    mut material_query: Query<&mut Handle<StandardMaterial>>,
+) {
+    let target = interaction.current_interaction;
+    for (entity, highlighted) in highlighted.iter() {
+        if Some(entity) == target {
+            continue;
+        }
+        for (mesh, original) in highlighted.originals.iter() {
+            if let Ok(mut material) = material_query.get_mut(*mesh) {
+                *material = original.clone();
+            }
+        }
+        commands.entity(entity).remove::<Highlighted>();
+    }
+
+    let (target, highlight) = match target.and_then(|t| highlights.get(t).ok().map(|h| (t, h))) {
+        Some(target) => target,
+        None => return,
+    };
+    let mut originals = Vec::new();
+    for mesh in descendants(target, &children_query) {
+        if let Ok(mut material) = material_query.get_mut(mesh) {
+            if let Some(tinted) = materials.get(&*material).cloned() {
+                originals.push((mesh, material.clone()));
+                *material = materials.add(StandardMaterial {
+                    emissive: highlight.emissive,
+                    ..tinted
+                });
+            }
+        }
+    }
+    // a model still loading is tried again next frame
+    if !originals.is_empty() {
+        commands.entity(target).insert(Highlighted { originals });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn start_interaction(
+    mut commands: Commands,
     inputs: Res<input::Inputs>,
-    interaction: Res<Interaction>,
+    mut interaction: ResMut<Interaction>,
+    interactables: Query<&Interactable>,
+    speakers: Query<(), With<dialogue::DialogueSource>>,
     mut app_state: ResMut<State<super::AppState>>,
+    mut bark_evw: EventWriter<ui::ShowBarkEvent>,
+    mut pickup_evw: EventWriter<ItemPickedUp>,
+    mut custom_evw: EventWriter<InteractionEvent>,
 ) {
-    if inputs.interact {
-        if let Some(_) = interaction.current_interaction {
-            app_state.set(super::AppState::Dialogue).unwrap();
+    if !inputs.interact {
+        return;
+    }
+    let (entity, interactable) = match interaction
+        .current_interaction
+        .and_then(|entity| interactables.get(entity).ok().map(|i| (entity, i)))
+    {
+        Some(target) => target,
+        None => return,
+    };
+    match &interactable.kind {
+        InteractionKind::Talk => {
+            if speakers.get(entity).is_ok() {
+                app_state.set(super::AppState::Dialogue).unwrap();
+            } else {
+                warn!("{:?} can be talked to, but has no DialogueSource", entity);
+            }
+        }
+        InteractionKind::Inspect(description) => bark_evw.send(ui::ShowBarkEvent {
+            target: entity,
+            speaker: None,
+            text: description.clone(),
+            duration: INSPECT_SECONDS,
+        }),
+        InteractionKind::PickUp(item) => {
+            pickup_evw.send(ItemPickedUp { item: item.clone() });
+            commands.entity(entity).despawn_recursive();
+            interaction.current_interaction = None;
         }
+        InteractionKind::Custom(name) => custom_evw.send(InteractionEvent {
+            name: name.clone(),
+            entity,
+        }),
     }
 }
+
+#[test]
+pub fn test_nearest_hit() {
+    let near = Entity::from_raw(1);
+    let far = Entity::from_raw(2);
+    let aside = Entity::from_raw(3);
+    let targets = [
+        (far, Vec3::new(0.0, 0.0, -6.0), InteractShape::Sphere(1.0)),
+        (
+            near,
+            Vec3::new(0.0, 0.0, -3.0),
+            InteractShape::Box(Vec3::ONE),
+        ),
+        (
+            aside,
+            Vec3::new(3.0, 0.0, -0.5),
+            InteractShape::Box(Vec3::ONE),
+        ),
+    ];
+    let origin = Vec3::ZERO;
+    let forward = -Vec3::Z;
+    // whichever is first in the query, the closest is picked
+    assert_eq!(
+        nearest_hit(origin, forward, 10.0, targets.iter().copied()),
+        Some(near)
+    );
+    assert_eq!(
+        nearest_hit(origin, forward, 10.0, targets.iter().rev().copied()),
+        Some(near)
+    );
+    assert_eq!(
+        nearest_hit(origin, forward, 1.5, targets.iter().copied()),
+        None
+    );
+    assert_eq!(
+        nearest_hit(origin, Vec3::X, 10.0, targets.iter().copied()),
+        Some(aside)
+    );
+    // nothing behind the camera
+    assert_eq!(
+        nearest_hit(origin, Vec3::Z, 10.0, targets.iter().copied()),
+        None
+    );
+
+    assert_eq!(
+        InteractShape::Box(Vec3::ONE).ray_distance(origin, forward, Vec3::new(0.0, 0.0, -3.0)),
+        Some(2.0)
+    );
+    assert_eq!(
+        InteractShape::Sphere(1.0).ray_distance(origin, forward, Vec3::new(0.0, 0.0, -6.0)),
+        Some(5.0)
+    );
+    // from inside
+    assert_eq!(
+        InteractShape::Sphere(1.0).ray_distance(origin, forward, Vec3::ZERO),
+        Some(0.0)
+    );
+}
@@ -13,6 +13,15 @@ mod ui;
 pub enum AppState {
     Game,
     Dialogue,
+    // the conversation is over, the game carries on once the dialogue box has animated away
+    ClosingDialogue,
+}
+
+// Queues a move to another state, false if it's already there or another system got in first
+// this frame
+pub fn queue_state(app_state: &mut State<AppState>, state: AppState) -> bool {
+    // set only fails with AlreadyInState or StateAlreadyQueued
+    app_state.set(state).is_ok()
 }
 
 fn main() {
@@ -21,21 +30,31 @@ fn main() {
         .add_plugin(input::InputPlugin)
         .add_plugin(ui::UIPlugin)
         .add_plugin(dialogue::DialoguePlugin)
+        .add_plugin(camera::CameraPlugin)
         .insert_resource(interact::Interaction::default())
+        .init_resource::<interact::InteractionSettings>()
+        .add_event::<interact::ItemPickedUp>()
+        .add_event::<interact::InteractionEvent>()
         .add_startup_system(setup_game_world)
-        .add_startup_system(camera::startup_spawn_camera)
         .add_startup_system(mobs::setup_spawn_mob)
         .add_startup_system(lock_cursor)
+        .add_startup_system(watch_for_asset_changes)
         .add_system(bevy::input::system::exit_on_esc_system)
         // game state
         .add_state(AppState::Game)
         .add_system_set(
             SystemSet::on_update(AppState::Game)
-                .with_system(camera::camera_handle_input.after("input").before("physics"))
                 .with_system(physics::apply_velocity.label("physics").after("input"))
-                .with_system(interact::check_interactable.after("physics"))
+                .with_system(
+                    interact::check_interactable
+                        .label("interact-target")
+                        .after("physics"),
+                )
+                .with_system(interact::highlight_target.after("interact-target"))
                 .with_system(interact::start_interaction.after("input")),
         )
+        .add_system(remember_pickups)
+        .add_system(flip_light_switches)
         .run();
 }
 
@@ -50,10 +69,60 @@ fn setup_game_world(
         material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
         ..Default::default()
     });
+    // a note left lying around
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Box::new(0.3, 0.02, 0.4))),
+            material: materials.add(Color::rgb(0.9, 0.88, 0.8).into()),
+            transform: Transform::from_xyz(1.5, 0.01, 0.5),
+            ..Default::default()
+        })
+        .insert(interact::InteractionPrompt("Read note".to_string()))
+        .insert(interact::InteractionHighlight {
+            emissive: Color::rgb(0.2, 0.2, 0.2),
+        })
+        .insert(interact::Interactable {
+            shape: interact::InteractShape::Sphere(0.4),
+            kind: interact::InteractionKind::Inspect(
+                "\"Do not touch the edges.\" The rest is scribbled out.".to_string(),
+            ),
+        });
+    // a key for the dialogue to notice
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Box::new(0.1, 0.02, 0.25))),
+            material: materials.add(Color::rgb(0.85, 0.7, 0.2).into()),
+            transform: Transform::from_xyz(-1.5, 0.01, 1.0),
+            ..Default::default()
+        })
+        .insert(interact::InteractionPrompt("Pick up key".to_string()))
+        .insert(interact::InteractionHighlight {
+            emissive: Color::rgb(0.3, 0.25, 0.05),
+        })
+        .insert(interact::Interactable {
+            shape: interact::InteractShape::Sphere(0.3),
+            kind: interact::InteractionKind::PickUp("key".to_string()),
+        });
+    // a switch for the light
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Box::new(0.2, 0.6, 0.2))),
+            material: materials.add(Color::rgb(0.4, 0.4, 0.45).into()),
+            transform: Transform::from_xyz(-2.0, 0.3, -2.0),
+            ..Default::default()
+        })
+        .insert(interact::InteractionPrompt("Flip switch".to_string()))
+        .insert(interact::InteractionHighlight {
+            emissive: Color::rgb(0.2, 0.2, 0.2),
+        })
+        .insert(interact::Interactable {
+            shape: interact::InteractShape::Box(Vec3::new(0.2, 0.6, 0.2)),
+            kind: interact::InteractionKind::Custom(LIGHT_SWITCH.to_string()),
+        });
     // light
     commands.spawn_bundle(PointLightBundle {
         point_light: PointLight {
-            intensity: 1500.0,
+            intensity: LIGHT_INTENSITY,
             shadows_enabled: true,
             ..Default::default()
         },
@@ -62,6 +131,45 @@ fn setup_game_world(
     });
 }
 
+// Dialogue can check for picked up items with `has_<item>`, e.g. `if: has_key`
+fn remember_pickups(
+    mut evr: EventReader<interact::ItemPickedUp>,
+    mut vars: ResMut<dialogue::DialogueVariables>,
+) {
+    for e in evr.iter() {
+        vars.set(
+            &format!("has_{}", e.item),
+            dialogue::DialogueValue::Bool(true),
+        );
+    }
+}
+
+const LIGHT_SWITCH: &str = "light_switch";
+const LIGHT_INTENSITY: f32 = 1500.0;
+
+fn flip_light_switches(
+    mut evr: EventReader<interact::InteractionEvent>,
+    mut lights: Query<&mut PointLight>,
+) {
+    for _ in evr.iter().filter(|e| e.name == LIGHT_SWITCH) {
+        for mut light in lights.iter_mut() {
+            light.intensity = if light.intensity > 0.0 {
+                0.0
+            } else {
+                LIGHT_INTENSITY
+            };
+        }
+    }
+}
+
+// Lets dialogue and other assets be edited while the game is running
+fn watch_for_asset_changes(asset_server: Res<AssetServer>) {
+    if let Err(e) = asset_server.watch_for_changes() {
+        // e.g. built without the filesystem_watcher feature
+        warn!("carrying on without hot reloading: {:?}", e);
+    }
+}
+
 pub fn lock_cursor(mut windows: ResMut<Windows>) {
     let win = windows.get_primary_mut().unwrap();
     win.set_cursor_lock_mode(true);
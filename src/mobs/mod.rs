@@ -1,4 +1,4 @@
-use crate::interact;
+use crate::{dialogue, interact};
 use bevy::prelude::*;
 pub fn setup_spawn_mob(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands
@@ -9,5 +9,17 @@ pub fn setup_spawn_mob(mut commands: Commands, asset_server: Res<AssetServer>) {
         .with_children(|parent| {
             parent.spawn_scene(asset_server.load("models/cube.gltf#Scene0"));
         })
-        .insert(interact::Interactable(Vec3::ONE * 1.0));
+        .insert(interact::InteractionPrompt("Talk to Cube".to_string()))
+        .insert(interact::InteractionHighlight {
+            emissive: Color::rgb(0.15, 0.2, 0.25),
+        })
+        .insert(interact::Interactable {
+            shape: interact::InteractShape::Box(Vec3::ONE * 1.0),
+            kind: interact::InteractionKind::Talk,
+        })
+        .insert(dialogue::DialogueCharacter("cube".to_string()))
+        .insert(
+            dialogue::DialogueSource::new(&asset_server, "dialogue/cube.dialogue.yaml", "default")
+                .with_theme(&asset_server, "ui/cube.theme.yaml"),
+        );
 }
@@ -0,0 +1,219 @@
+use bevy::prelude::*;
+
+use super::{
+    DialogueRootElement, DialogueTextElement, PanelAnchor, PanelFilter, ResponseButtonElement,
+    TextReveal, UIData,
+};
+
+// Resource with how long the dialogue box's animations take, in seconds. 0 turns one off.
+pub struct DialogueAnimationSettings {
+    // the panel sliding in from its edge of the screen
+    pub open_seconds: f32,
+    // and back out, before the game carries on
+    pub close_seconds: f32,
+    // each new line fading in
+    pub text_fade_seconds: f32,
+    // each response growing in
+    pub response_seconds: f32,
+    // between one response starting to appear and the next
+    pub response_stagger_seconds: f32,
+}
+
+impl Default for DialogueAnimationSettings {
+    fn default() -> Self {
+        Self {
+            open_seconds: 0.25,
+            close_seconds: 0.2,
+            text_fade_seconds: 0.2,
+            response_seconds: 0.15,
+            response_stagger_seconds: 0.06,
+        }
+    }
+}
+
+// Eases a value from one number to another, what it drives depends on the entity it's on
+#[derive(Component, Clone, Debug)]
+pub(super) struct Tween {
+    from: f32,
+    to: f32,
+    duration: f32,
+    // negative while delayed
+    elapsed: f32,
+}
+
+impl Tween {
+    pub fn new(from: f32, to: f32, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            elapsed: 0.0,
+        }
+    }
+
+    // Takes `duration` to cover the whole way from 0 to 1, and less for part of it, so e.g.
+    // closing a half open panel takes half as long
+    pub fn between(from: f32, to: f32, duration: f32) -> Self {
+        Self::new(from, to, duration * (to - from).abs())
+    }
+
+    pub fn delayed(mut self, seconds: f32) -> Self {
+        self.elapsed = -seconds;
+        self
+    }
+
+    fn advance(&mut self, delta: f32) {
+        self.elapsed += delta;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    pub fn value(&self) -> f32 {
+        let t = if self.duration <= 0.0 {
+            1.0
+        } else {
+            (self.elapsed / self.duration).clamp(0.0, 1.0)
+        };
+        // ease out cubic
+        let eased = 1.0 - (1.0 - t).powi(3);
+        self.from + (self.to - self.from) * eased
+    }
+}
+
+pub(super) fn advance_tweens(time: Res<Time>, mut query: Query<&mut Tween>) {
+    for mut tween in query.iter_mut() {
+        // only touched while running, so Changed<Tween> means there's something to redraw
+        if !tween.is_finished() {
+            tween.advance(time.delta_seconds());
+        }
+    }
+}
+
+pub(super) fn open_dialogue_ui(
+    mut commands: Commands,
+    settings: Res<DialogueAnimationSettings>,
+    mut root_query: Query<&mut Transform, With<DialogueRootElement>>,
+    panel_query: Query<(Entity, Option<&Tween>), PanelFilter>,
+) {
+    for mut root_tx in root_query.iter_mut() {
+        root_tx.scale = Vec3::ONE;
+    }
+    for (panel, tween) in panel_query.iter() {
+        let from = tween.map_or(0.0, |tween| tween.value());
+        commands
+            .entity(panel)
+            .insert(Tween::between(from, 1.0, settings.open_seconds));
+    }
+}
+
+pub(super) fn close_dialogue_ui(
+    mut commands: Commands,
+    settings: Res<DialogueAnimationSettings>,
+    panel_query: Query<(Entity, Option<&Tween>), PanelFilter>,
+    text_query: Query<Entity, With<DialogueTextElement>>,
+) {
+    for (panel, tween) in panel_query.iter() {
+        let from = tween.map_or(1.0, |tween| tween.value());
+        commands
+            .entity(panel)
+            .insert(Tween::between(from, 0.0, settings.close_seconds));
+    }
+    for text in text_query.iter() {
+        commands
+            .entity(text)
+            .insert(Tween::new(1.0, 0.0, settings.close_seconds));
+    }
+}
+
+// Hands control back to the game once the panel is out of sight
+pub(super) fn finish_closing_dialogue(
+    panel_query: Query<&Tween, PanelFilter>,
+    mut app_state: ResMut<State<crate::AppState>>,
+) {
+    // the closing tween may not have replaced the opening one yet
+    if panel_query
+        .iter()
+        .all(|tween| tween.is_finished() && tween.value() <= 0.0)
+    {
+        crate::queue_state(&mut app_state, crate::AppState::Game);
+    }
+}
+
+// Also covers conversations which never got going, so the next one still slides in
+pub(super) fn hide_dialogue_ui(
+    mut commands: Commands,
+    mut root_query: Query<&mut Transform, With<DialogueRootElement>>,
+    panel_query: Query<Entity, PanelFilter>,
+    text_query: Query<Entity, With<DialogueTextElement>>,
+) {
+    for mut root_tx in root_query.iter_mut() {
+        root_tx.scale = Vec3::ZERO;
+    }
+    // back where the opening animations start from
+    for entity in panel_query.iter().chain(text_query.iter()) {
+        commands.entity(entity).insert(Tween::new(0.0, 0.0, 0.0));
+    }
+}
+
+// Runs after apply_dialogue_theme, which resets the panel's Style
+pub(super) fn slide_dialogue_panel(
+    ui_data: Res<UIData>,
+    mut panel_query: Query<(&Tween, &mut Style), PanelFilter>,
+) {
+    for (tween, mut style) in panel_query.iter_mut() {
+        if tween.is_finished() && !ui_data.is_changed() {
+            continue;
+        }
+        // percentages of the screen, like the panel's height
+        let offset = Val::Percent(-(1.0 - tween.value()) * ui_data.theme.panel_height);
+        style.position = match ui_data.theme.panel_anchor {
+            PanelAnchor::Bottom => Rect {
+                bottom: offset,
+                ..Default::default()
+            },
+            PanelAnchor::Top => Rect {
+                top: offset,
+                ..Default::default()
+            },
+        };
+    }
+}
+
+pub(super) fn fade_dialogue_text(
+    mut reveal: ResMut<TextReveal>,
+    query: Query<&Tween, (With<DialogueTextElement>, Changed<Tween>)>,
+) {
+    for tween in query.iter() {
+        reveal.set_opacity(tween.value());
+    }
+}
+
+type GrowingButtonFilter = (With<ResponseButtonElement>, Changed<Tween>);
+
+pub(super) fn grow_response_buttons(
+    mut query: Query<(&Tween, &mut Transform), GrowingButtonFilter>,
+) {
+    for (tween, mut transform) in query.iter_mut() {
+        transform.scale = Vec3::splat(tween.value());
+    }
+}
+
+#[test]
+pub fn test_tween() {
+    let mut tween = Tween::new(0.0, 2.0, 1.0).delayed(0.5);
+    tween.advance(0.5);
+    assert_eq!(tween.value(), 0.0);
+    tween.advance(0.5);
+    // eased, so over halfway there by half time
+    assert_eq!(tween.value(), 1.75);
+    tween.advance(1.0);
+    assert!(tween.is_finished());
+    assert_eq!(tween.value(), 2.0);
+
+    // closing a half open panel takes half as long
+    let tween = Tween::between(0.5, 0.0, 0.2);
+    assert_eq!(tween.duration, 0.1);
+    assert_eq!(Tween::new(1.0, 0.0, 0.0).value(), 0.0);
+}
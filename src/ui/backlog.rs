@@ -0,0 +1,204 @@
+use bevy::prelude::*;
+
+use super::{SpeakerNameData, UIData};
+use crate::input;
+
+// how many lines of the backlog fit on screen at once
+const VISIBLE_LINES: usize = 14;
+
+#[derive(Component)]
+pub(super) struct BacklogPanelElement;
+
+#[derive(Component)]
+pub(super) struct BacklogTextElement;
+
+#[derive(Clone)]
+pub struct BacklogLineData {
+    // None for lines nobody in particular said
+    pub speaker: Option<SpeakerNameData>,
+    pub text: std::string::String,
+}
+
+// Adds a line to the bottom of the backlog panel
+pub struct AppendBacklogEvent(pub BacklogLineData);
+
+// Resource holding what the backlog panel shows
+#[derive(Default)]
+pub(super) struct Backlog {
+    lines: Vec<BacklogLineData>,
+    // lines scrolled up from the newest one
+    scroll: usize,
+    open: bool,
+}
+
+impl Backlog {
+    fn scroll_by(&mut self, lines: i32) {
+        let max = self.lines.len().saturating_sub(VISIBLE_LINES);
+        self.scroll = (self.scroll as i32 + lines).clamp(0, max as i32) as usize;
+    }
+
+    fn visible_lines(&self) -> &[BacklogLineData] {
+        let end = self.lines.len() - self.scroll;
+        &self.lines[end.saturating_sub(VISIBLE_LINES)..end]
+    }
+}
+
+impl UIData {
+    pub(super) fn build_backlog_panel(&self) -> NodeBundle {
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(5.0),
+                    top: Val::Percent(5.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(90.0), Val::Percent(40.0)),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::FlexStart,
+                padding: Rect::all(Val::Px(10.0)),
+                ..Default::default()
+            },
+            color: Color::rgba(0.08, 0.08, 0.08, 0.9).into(),
+            transform: Transform::from_scale(Vec3::ZERO),
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn build_backlog_text(&self) -> TextBundle {
+        TextBundle {
+            text: Text {
+                sections: vec![],
+                alignment: TextAlignment {
+                    horizontal: HorizontalAlign::Left,
+                    vertical: VerticalAlign::Bottom,
+                },
+            },
+            ..Default::default()
+        }
+    }
+
+    fn build_backlog_sections(&self, lines: &[BacklogLineData]) -> Vec<TextSection> {
+        let style = TextStyle {
+            font: self.font_handle.clone(),
+            font_size: 16.0,
+            color: Color::rgb(0.8, 0.8, 0.8),
+        };
+        let mut sections = Vec::new();
+        for line in lines {
+            if let Some(speaker) = &line.speaker {
+                sections.push(TextSection {
+                    value: format!("{}: ", speaker.name),
+                    style: TextStyle {
+                        color: speaker.color,
+                        ..style.clone()
+                    },
+                });
+            }
+            sections.push(TextSection {
+                value: format!("{}\n", line.text),
+                style: style.clone(),
+            });
+        }
+        sections
+    }
+}
+
+pub(super) fn setup_backlog_ui(mut commands: Commands, ui_data: Res<UIData>) {
+    commands
+        .spawn_bundle(ui_data.build_backlog_panel())
+        .insert(BacklogPanelElement)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ui_data.build_backlog_text())
+                .insert(BacklogTextElement);
+        });
+}
+
+pub(super) fn update_backlog(
+    inputs: Res<input::Inputs>,
+    mut evr: EventReader<AppendBacklogEvent>,
+    mut backlog: ResMut<Backlog>,
+) {
+    for e in evr.iter() {
+        backlog.lines.push(e.0.clone());
+        // keep the same lines on screen if scrolled up
+        if backlog.scroll > 0 {
+            backlog.scroll += 1;
+        }
+    }
+    if inputs.toggle_backlog {
+        backlog.open = !backlog.open;
+        backlog.scroll = 0;
+    }
+    if backlog.open && inputs.backlog_scroll != 0 {
+        backlog.scroll_by(inputs.backlog_scroll);
+    }
+}
+
+pub(super) fn refresh_backlog_ui(
+    backlog: Res<Backlog>,
+    ui_data: Res<UIData>,
+    mut panel_query: Query<&mut Transform, With<BacklogPanelElement>>,
+    mut text_query: Query<&mut Text, With<BacklogTextElement>>,
+) {
+    if !backlog.is_changed() {
+        return;
+    }
+    for mut panel_tx in panel_query.iter_mut() {
+        panel_tx.scale = if backlog.open { Vec3::ONE } else { Vec3::ZERO };
+    }
+    if backlog.open {
+        for mut text in text_query.iter_mut() {
+            text.sections = ui_data.build_backlog_sections(backlog.visible_lines());
+        }
+    }
+}
+
+#[test]
+pub fn test_backlog_scrolling() {
+    let mut backlog = Backlog::default();
+    for i in 0..20 {
+        backlog.lines.push(BacklogLineData {
+            speaker: None,
+            text: i.to_string(),
+        });
+    }
+    assert_eq!(backlog.visible_lines()[0].text, "6");
+    backlog.scroll_by(4);
+    assert_eq!(backlog.visible_lines()[0].text, "2");
+    assert_eq!(backlog.visible_lines()[13].text, "15");
+    // can't scroll past the oldest line
+    backlog.scroll_by(100);
+    assert_eq!(backlog.visible_lines()[0].text, "0");
+    backlog.scroll_by(-100);
+    assert_eq!(backlog.visible_lines()[13].text, "19");
+
+    // a frame without any scrolling input leaves an open backlog where it was
+    let mut world = World::new();
+    world.insert_resource(input::Inputs::default());
+    world.insert_resource(input::InputBindings::default());
+    world.insert_resource(Input::<KeyCode>::default());
+    world.insert_resource(bevy::input::gamepad::Gamepads::default());
+    world.insert_resource(Input::<GamepadButton>::default());
+    world.insert_resource(bevy::app::Events::<bevy::input::mouse::MouseMotion>::default());
+    world.insert_resource(bevy::app::Events::<bevy::input::mouse::MouseWheel>::default());
+    world.insert_resource(bevy::app::Events::<AppendBacklogEvent>::default());
+    backlog.open = true;
+    backlog.scroll_by(3);
+    world.insert_resource(backlog);
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(input::update_inputs.label("input"));
+    stage.add_system(update_backlog.after("input"));
+    for _ in 0..3 {
+        stage.run(&mut world);
+        assert_eq!(
+            world
+                .get_resource::<input::Inputs>()
+                .unwrap()
+                .backlog_scroll,
+            0
+        );
+        assert_eq!(world.get_resource::<Backlog>().unwrap().scroll, 3);
+    }
+}
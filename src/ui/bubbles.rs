@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+
+use super::markup::{self, MarkupSpan};
+use super::{SpeakerNameData, UIData};
+use crate::camera;
+
+// how far above the barking entity's origin its bubble floats
+const BUBBLE_HEIGHT: f32 = 1.2;
+// seconds spent fading out at the end of a bubble's duration
+const FADE_TIME: f32 = 0.5;
+
+// Floats a line above an entity for `duration` seconds, replacing any bubble it already has
+pub struct ShowBarkEvent {
+    pub target: Entity,
+    pub speaker: Option<SpeakerNameData>,
+    pub text: std::string::String,
+    pub duration: f32,
+}
+
+#[derive(Component)]
+pub(super) struct SpeechBubble {
+    target: Entity,
+    speaker: Option<SpeakerNameData>,
+    spans: Vec<MarkupSpan>,
+    // seconds left before it's gone
+    remaining: f32,
+}
+
+impl UIData {
+    fn build_speech_bubble(&self) -> NodeBundle {
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                max_size: Size::new(Val::Px(400.0), Val::Undefined),
+                padding: Rect::all(Val::Px(8.0)),
+                // hidden until it's been placed over its entity
+                display: Display::None,
+                ..Default::default()
+            },
+            color: self.theme.panel_color.into(),
+            ..Default::default()
+        }
+    }
+
+    fn speech_bubble_sections(&self, bubble: &SpeechBubble, time: f32) -> Vec<TextSection> {
+        let mut sections = markup::markup_sections(&bubble.spans, usize::MAX, self, time);
+        if let Some(speaker) = &bubble.speaker {
+            sections.insert(
+                0,
+                TextSection {
+                    value: format!("{}: ", speaker.name),
+                    style: TextStyle {
+                        color: speaker.color,
+                        ..self.speaker_name_style()
+                    },
+                },
+            );
+        }
+        sections
+    }
+}
+
+pub(super) fn spawn_speech_bubbles(
+    mut commands: Commands,
+    mut evr: EventReader<ShowBarkEvent>,
+    bubbles: Query<(Entity, &SpeechBubble)>,
+    ui_data: Res<UIData>,
+) {
+    for e in evr.iter() {
+        for (entity, bubble) in bubbles.iter() {
+            if bubble.target == e.target {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        commands
+            .spawn_bundle(ui_data.build_speech_bubble())
+            .insert(SpeechBubble {
+                target: e.target,
+                speaker: e.speaker.clone(),
+                spans: markup::parse_markup(&e.text),
+                remaining: e.duration,
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::default());
+            });
+    }
+}
+
+// Keeps bubbles over their entities as the camera moves, fading them out as they expire
+#[allow(clippy::too_many_arguments)]
+pub(super) fn update_speech_bubbles(
+    mut commands: Commands,
+    time: Res<Time>,
+    windows: Res<Windows>,
+    ui_data: Res<UIData>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<camera::MainCamera>>,
+    targets: Query<&GlobalTransform>,
+    mut bubbles: Query<(
+        Entity,
+        &mut SpeechBubble,
+        &mut Style,
+        &mut UiColor,
+        &Node,
+        &Children,
+    )>,
+    mut text_query: Query<&mut Text>,
+) {
+    let (camera, camera_transform) = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    for (entity, mut bubble, mut style, mut color, node, children) in bubbles.iter_mut() {
+        bubble.remaining -= time.delta_seconds();
+        let target = match targets.get(bubble.target) {
+            Ok(target) if bubble.remaining > 0.0 => target,
+            _ => {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+        };
+
+        // off screen or behind the camera
+        let screen = match camera.world_to_screen(
+            &windows,
+            camera_transform,
+            target.translation + Vec3::Y * BUBBLE_HEIGHT,
+        ) {
+            Some(screen) => screen,
+            None => {
+                style.display = Display::None;
+                continue;
+            }
+        };
+        style.display = Display::Flex;
+        // centred over the entity, using last frame's size
+        style.position = Rect {
+            left: Val::Px(screen.x - node.size.x / 2.0),
+            bottom: Val::Px(screen.y),
+            ..Default::default()
+        };
+
+        let alpha = (bubble.remaining / FADE_TIME).min(1.0);
+        color.0.set_a(ui_data.theme.panel_color.a() * alpha);
+        if let Ok(mut text) = text_query.get_mut(children[0]) {
+            text.sections =
+                ui_data.speech_bubble_sections(&bubble, time.seconds_since_startup() as f32);
+            for section in text.sections.iter_mut() {
+                let a = section.style.color.a();
+                section.style.color.set_a(a * alpha);
+            }
+        }
+    }
+}
+
+// Barks give way to a conversation
+pub(super) fn clear_speech_bubbles(
+    mut commands: Commands,
+    bubbles: Query<Entity, With<SpeechBubble>>,
+) {
+    for entity in bubbles.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
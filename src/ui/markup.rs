@@ -0,0 +1,196 @@
+// Inline markup for dialogue text, e.g. `I [b]told[/b] you, [color=#f00]don't[/color]!`
+//
+// [b]...[/b]              bold
+// [i]...[/i]              italic
+// [color=#rrggbb]...[/color]
+// [size=28]...[/size]
+// [wave]...[/wave]        ripples while shown
+//
+// Tags nest, and anything in brackets which isn't a known tag is left in the text as written.
+
+use bevy::prelude::*;
+
+use super::UIData;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarkupStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub color: Option<Color>,
+    pub size: Option<f32>,
+    pub wave: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarkupSpan {
+    pub text: std::string::String,
+    pub style: MarkupStyle,
+}
+
+pub fn parse_markup(source: &str) -> Vec<MarkupSpan> {
+    let mut spans: Vec<MarkupSpan> = Vec::new();
+    let mut style = MarkupStyle::default();
+    // open tags, with the style to go back to when each one closes
+    let mut open: Vec<(&str, MarkupStyle)> = Vec::new();
+    let mut text = std::string::String::new();
+
+    let mut rest = source;
+    while let Some(start) = rest.find('[') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(']') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[1..end];
+        let restyled = if let Some(name) = tag.strip_prefix('/') {
+            open.iter().rposition(|(open, _)| *open == name).map(|i| {
+                let previous = open[i].1.clone();
+                open.truncate(i);
+                previous
+            })
+        } else {
+            open_tag(tag, &style).map(|opened| {
+                let name = tag.split('=').next().unwrap_or(tag);
+                open.push((name, style.clone()));
+                opened
+            })
+        };
+        match restyled {
+            Some(restyled) => {
+                push_span(&mut spans, std::mem::take(&mut text), &style);
+                style = restyled;
+            }
+            None => text.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    push_span(&mut spans, text, &style);
+    spans
+}
+
+// The style inside a tag, or None if it isn't one we know
+fn open_tag(tag: &str, style: &MarkupStyle) -> Option<MarkupStyle> {
+    let mut style = style.clone();
+    match tag.split_once('=') {
+        None if tag == "b" => style.bold = true,
+        None if tag == "i" => style.italic = true,
+        None if tag == "wave" => style.wave = true,
+        Some(("color", hex)) => style.color = Some(Color::hex(hex.trim_start_matches('#')).ok()?),
+        Some(("size", size)) => style.size = Some(size.parse().ok()?),
+        _ => return None,
+    }
+    Some(style)
+}
+
+fn push_span(spans: &mut Vec<MarkupSpan>, text: std::string::String, style: &MarkupStyle) {
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.style == *style => last.text.push_str(&text),
+        _ => spans.push(MarkupSpan {
+            text,
+            style: style.clone(),
+        }),
+    }
+}
+
+// For anything which wants the words without the formatting
+pub fn strip_markup(source: &str) -> std::string::String {
+    parse_markup(source)
+        .into_iter()
+        .map(|span| span.text)
+        .collect()
+}
+
+// Text sections for the first `revealed` characters of `spans`. `time` drives the wave
+// animation; bevy can't move single glyphs, so waves ripple through the text's brightness.
+pub(super) fn markup_sections(
+    spans: &[MarkupSpan],
+    revealed: usize,
+    ui_data: &UIData,
+    time: f32,
+) -> Vec<TextSection> {
+    let base = ui_data.dialogue_text_style();
+    let mut sections = Vec::new();
+    let mut remaining = revealed;
+    for span in spans {
+        if remaining == 0 {
+            break;
+        }
+        let text: std::string::String = span.text.chars().take(remaining).collect();
+        remaining -= text.chars().count();
+
+        // without faces of their own, bold and italic stand out by colour instead
+        let emphasis = match (span.style.bold, span.style.italic) {
+            (true, _) if ui_data.theme.bold_font.is_none() => Some(ui_data.theme.bold_color),
+            (false, true) if ui_data.theme.italic_font.is_none() => {
+                Some(ui_data.theme.italic_color)
+            }
+            _ => None,
+        };
+        let style = TextStyle {
+            font: match (span.style.bold, span.style.italic) {
+                (true, _) => ui_data.bold_font_handle.clone(),
+                (false, true) => ui_data.italic_font_handle.clone(),
+                (false, false) => base.font.clone(),
+            },
+            font_size: span.style.size.unwrap_or(base.font_size),
+            color: span.style.color.or(emphasis).unwrap_or(base.color),
+        };
+        if span.style.wave {
+            for (i, c) in text.chars().enumerate() {
+                let brightness = 0.75 + 0.25 * (time * 6.0 - i as f32 * 0.6).sin();
+                let color = style.color;
+                sections.push(TextSection {
+                    value: c.to_string(),
+                    style: TextStyle {
+                        color: Color::rgba(
+                            color.r() * brightness,
+                            color.g() * brightness,
+                            color.b() * brightness,
+                            color.a(),
+                        ),
+                        ..style.clone()
+                    },
+                });
+            }
+        } else {
+            sections.push(TextSection { value: text, style });
+        }
+    }
+    if sections.is_empty() {
+        // Text needs at least one section to be updated later
+        sections.push(TextSection {
+            value: std::string::String::new(),
+            style: base,
+        });
+    }
+    sections
+}
+
+#[test]
+pub fn test_parse_markup() {
+    let spans = parse_markup("I [b]told[/b] you, [color=#f00][size=28]don't[/size][/color]!");
+    assert_eq!(spans.len(), 5);
+    assert_eq!(spans[0].text, "I ");
+    assert_eq!(spans[1].text, "told");
+    assert!(spans[1].style.bold);
+    assert_eq!(spans[3].text, "don't");
+    assert_eq!(spans[3].style.color, Some(Color::rgb(1.0, 0.0, 0.0)));
+    assert_eq!(spans[3].style.size, Some(28.0));
+    assert_eq!(spans[4].text, "!");
+    assert_eq!(spans[4].style, MarkupStyle::default());
+
+    // closing an outer tag closes everything inside it
+    let spans = parse_markup("[wave][i]oo[/wave]oh");
+    assert!(spans[0].style.wave && spans[0].style.italic);
+    assert_eq!(spans[1].style, MarkupStyle::default());
+
+    assert_eq!(
+        strip_markup("[b]1[/b] [x] [/i] [size=big] [color=#f00"),
+        "1 [x] [/i] [size=big] [color=#f00"
+    );
+}
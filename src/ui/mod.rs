@@ -1,17 +1,39 @@
 use bevy::prelude::*;
-
-const NORMAL_BUTTON: Color = Color::rgb(0.98, 0.98, 0.98);
-const HOVERED_BUTTON: Color = Color::rgb(0.70, 0.70, 0.70);
+mod animation;
+mod backlog;
+mod bubbles;
+mod markup;
+mod navigation;
+mod prompt;
+mod responses;
+mod theme;
+mod typewriter;
+
+pub use animation::DialogueAnimationSettings;
+pub use backlog::{AppendBacklogEvent, BacklogLineData};
+pub use bubbles::ShowBarkEvent;
+pub use markup::strip_markup;
+pub use theme::{DialogueTheme, DialogueThemeFile, PanelAnchor, UseDialogueTheme};
+pub use typewriter::{TextReveal, TextRevealFinished, TypewriterSettings};
 
 #[derive(Component)]
 struct DialogueRootElement;
 
+#[derive(Component)]
+struct DialoguePanelElement;
+
 #[derive(Component)]
 struct DialogueTextElement;
 
+#[derive(Component)]
+struct DialogueSpeakerElement;
+
 #[derive(Component)]
 struct ResponseContainerElement;
 
+#[derive(Component)]
+struct DialoguePortraitElement;
+
 #[derive(Bundle)]
 struct UIDialogueRootBundle {
     tag: DialogueRootElement,
@@ -26,6 +48,13 @@ struct DialogueContainerElementBundle {
     node_bundle: NodeBundle,
 }
 
+#[derive(Bundle)]
+struct DialogueSpeakerElementBundle {
+    tag: DialogueSpeakerElement,
+    #[bundle]
+    text_bundle: TextBundle,
+}
+
 #[derive(Bundle)]
 struct DialogueTextElementBundle {
     tag: DialogueTextElement,
@@ -35,8 +64,9 @@ struct DialogueTextElementBundle {
 
 #[derive(Component)]
 struct ResponseButtonElement {
-    // index of the response in the current dialogue tree root
-    pub response_index: usize,
+    pub response: ResponseId,
+    // leads somewhere the player has already been shown, drawn dimmed
+    pub seen: bool,
 }
 
 #[derive(Bundle)]
@@ -46,29 +76,107 @@ struct ResponseButtonElementBundle {
     button_bundle: ButtonBundle,
 }
 
+// portraits are looked up as `portraits/<id>.png`, e.g. `portraits/cube/angry.png`
+const PORTRAIT_DIR: &str = "portraits";
+
 // Resource
 struct UIData {
+    // copy of the DialogueTheme resource the ui was last styled with
+    theme: DialogueTheme,
     font_handle: Handle<Font>,
+    // used by [b] and [i] markup
+    bold_font_handle: Handle<Font>,
+    italic_font_handle: Handle<Font>,
 }
 
 impl FromWorld for UIData {
     fn from_world(world: &mut World) -> Self {
+        let theme = world
+            .get_resource::<DialogueTheme>()
+            .cloned()
+            .unwrap_or_default();
         let server = world.get_resource::<AssetServer>().unwrap();
-        let font_handle = server.load("fonts/FiraCode-Regular.ttf");
-
-        Self { font_handle }
+        Self::new(theme, server)
     }
 }
 
 impl UIData {
+    fn new(theme: DialogueTheme, server: &AssetServer) -> Self {
+        let font_handle: Handle<Font> = server.load(theme.font.as_str());
+        let load_or_regular = |font: &Option<std::string::String>| match font {
+            Some(font) => server.load(font.as_str()),
+            None => font_handle.clone(),
+        };
+        Self {
+            bold_font_handle: load_or_regular(&theme.bold_font),
+            italic_font_handle: load_or_regular(&theme.italic_font),
+            font_handle,
+            theme,
+        }
+    }
+
+    fn dialogue_text_style(&self) -> TextStyle {
+        TextStyle {
+            font: self.font_handle.clone(),
+            font_size: self.theme.font_size,
+            color: self.theme.text_color,
+        }
+    }
+
+    fn speaker_name_style(&self) -> TextStyle {
+        TextStyle {
+            font: self.font_handle.clone(),
+            font_size: self.theme.name_font_size,
+            color: self.theme.text_color,
+        }
+    }
+
+    fn root_style(&self) -> Style {
+        Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            // ui is laid out bottom up
+            align_items: match self.theme.panel_anchor {
+                PanelAnchor::Bottom => AlignItems::FlexStart,
+                PanelAnchor::Top => AlignItems::FlexEnd,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn panel_style(&self) -> Style {
+        Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(self.theme.panel_height)),
+            flex_direction: FlexDirection::Row,
+            justify_content: JustifyContent::FlexStart,
+            align_items: AlignItems::FlexStart,
+            padding: Rect::all(Val::Percent(self.theme.panel_padding)),
+            ..Default::default()
+        }
+    }
+
+    // Hidden while the speaker has no portrait
+    fn portrait_style(&self, shown: bool) -> Style {
+        Style {
+            size: Size::new(
+                Val::Px(self.theme.portrait_size),
+                Val::Px(self.theme.portrait_size),
+            ),
+            // top of the panel
+            align_self: AlignSelf::FlexEnd,
+            margin: Rect {
+                right: Val::Px(15.0),
+                ..Default::default()
+            },
+            display: if shown { Display::Flex } else { Display::None },
+            ..Default::default()
+        }
+    }
+
     fn build_root_node(&self) -> UIDialogueRootBundle {
         UIDialogueRootBundle {
             tag: DialogueRootElement,
             node_bundle: NodeBundle {
-                style: Style {
-                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-                    ..Default::default()
-                },
+                style: self.root_style(),
                 color: Color::NONE.into(),
                 transform: Transform::from_scale(Vec3::ZERO),
                 ..Default::default()
@@ -77,16 +185,32 @@ impl UIData {
     }
 
     fn build_bottom_bar(&self) -> NodeBundle {
+        NodeBundle {
+            style: self.panel_style(),
+            color: self.theme.panel_color.into(),
+            ..Default::default()
+        }
+    }
+
+    fn build_portrait(&self) -> ImageBundle {
+        ImageBundle {
+            style: self.portrait_style(false),
+            ..Default::default()
+        }
+    }
+
+    // Column beside the portrait holding the name, text and responses
+    fn build_panel_content(&self) -> NodeBundle {
         NodeBundle {
             style: Style {
-                size: Size::new(Val::Percent(100.0), Val::Percent(50.0)),
+                size: Size::new(Val::Auto, Val::Percent(100.0)),
+                flex_grow: 1.0,
                 flex_direction: FlexDirection::Column,
                 justify_content: JustifyContent::FlexStart,
                 align_items: AlignItems::FlexStart,
-                padding: Rect::all(Val::Percent(5.0)),
                 ..Default::default()
             },
-            color: Color::rgb(0.13, 0.13, 0.13).into(),
+            color: Color::NONE.into(),
             ..Default::default()
         }
     }
@@ -102,7 +226,30 @@ impl UIData {
                     align_items: AlignItems::FlexStart,
                     ..Default::default()
                 },
-                color: Color::rgb(0.13, 0.13, 0.13).into(),
+                color: Color::NONE.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    // Name plate above the dialogue text, empty when nobody in particular is speaking
+    fn build_speaker_name(&self) -> DialogueSpeakerElementBundle {
+        DialogueSpeakerElementBundle {
+            tag: DialogueSpeakerElement,
+            text_bundle: TextBundle {
+                style: Style {
+                    margin: Rect {
+                        left: Val::Px(10.0),
+                        bottom: Val::Px(6.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "".to_string(),
+                    self.speaker_name_style(),
+                    Default::default(),
+                ),
                 ..Default::default()
             },
         }
@@ -128,11 +275,7 @@ impl UIData {
 
                 text: Text::with_section(
                     "...".to_string(),
-                    TextStyle {
-                        font: self.font_handle.clone(),
-                        font_size: 20.0,
-                        color: Color::rgb(0.9, 0.9, 0.9),
-                    },
+                    self.dialogue_text_style(),
                     TextAlignment {
                         horizontal: HorizontalAlign::Left,
                         vertical: VerticalAlign::Bottom,
@@ -143,9 +286,13 @@ impl UIData {
         }
     }
 
-    fn build_response_button(&self, response_index: usize) -> ResponseButtonElementBundle {
+    fn build_response_button(
+        &self,
+        response: ResponseId,
+        seen: bool,
+    ) -> ResponseButtonElementBundle {
         ResponseButtonElementBundle {
-            tag: ResponseButtonElement { response_index },
+            tag: ResponseButtonElement { response, seen },
             button_bundle: ButtonBundle {
                 style: Style {
                     // horizontally center child text
@@ -160,20 +307,46 @@ impl UIData {
         }
     }
 
-    fn build_response_button_text(&self, text: &str) -> TextBundle {
+    fn build_continue_hint(&self) -> TextBundle {
         TextBundle {
             text: Text::with_section(
-                format!("> {}", text),
+                "[space] continue".to_string(),
                 TextStyle {
                     font: self.font_handle.clone(),
-                    font_size: 20.0,
-                    color: Color::rgb(0.9, 0.9, 0.9),
+                    font_size: self.theme.hint_font_size,
+                    color: self.theme.hint_color,
                 },
                 Default::default(),
             ),
             ..Default::default()
         }
     }
+
+    // `number` is the response's shortcut key, if it has one
+    fn response_button_section(&self, number: Option<usize>, text: &str) -> TextSection {
+        let prefix = match number {
+            Some(number) if self.theme.numbered_buttons => format!("{}. ", number),
+            _ => self.theme.button_prefix.clone(),
+        };
+        TextSection {
+            value: format!("{}{}", prefix, strip_markup(text)),
+            style: TextStyle {
+                font: self.font_handle.clone(),
+                font_size: self.theme.button_font_size,
+                color: self.theme.button_color,
+            },
+        }
+    }
+
+    fn build_response_button_text(&self, number: Option<usize>, text: &str) -> TextBundle {
+        TextBundle {
+            text: Text {
+                sections: vec![self.response_button_section(number, text)],
+                alignment: Default::default(),
+            },
+            ..Default::default()
+        }
+    }
 }
 
 fn setup_dialogue_ui(mut commands: Commands, ui_data: Res<UIData>) {
@@ -183,126 +356,178 @@ fn setup_dialogue_ui(mut commands: Commands, ui_data: Res<UIData>) {
         .with_children(|parent| {
             parent
                 .spawn_bundle(ui_data.build_bottom_bar())
+                .insert(DialoguePanelElement)
                 .with_children(|parent| {
-                    parent.spawn_bundle(ui_data.build_response_container());
-                    parent.spawn_bundle(ui_data.build_dialogue_text());
+                    parent
+                        .spawn_bundle(ui_data.build_portrait())
+                        .insert(DialoguePortraitElement);
+                    parent
+                        .spawn_bundle(ui_data.build_panel_content())
+                        .with_children(|parent| {
+                            parent.spawn_bundle(ui_data.build_response_container());
+                            parent.spawn_bundle(ui_data.build_dialogue_text());
+                            parent.spawn_bundle(ui_data.build_speaker_name());
+                        });
                 });
         });
 }
 
-fn show_dialogue_ui(mut root_query: Query<&mut Transform, With<DialogueRootElement>>) {
-    for mut root_tx in root_query.iter_mut() {
-        root_tx.scale = Vec3::ONE;
+// the panel's and portrait's Styles mustn't overlap with the root's or each other's
+type PanelFilter = (With<DialoguePanelElement>, Without<DialogueRootElement>);
+type PortraitFilter = (
+    With<DialoguePortraitElement>,
+    Without<DialogueRootElement>,
+    Without<DialoguePanelElement>,
+);
+
+// Restyles the ui whenever the DialogueTheme resource changes. Response buttons and backlog
+// lines pick the new theme up as they're rebuilt.
+fn apply_dialogue_theme(
+    theme: Res<DialogueTheme>,
+    asset_server: Res<AssetServer>,
+    mut ui_data: ResMut<UIData>,
+    mut root_query: Query<&mut Style, With<DialogueRootElement>>,
+    mut panel_query: Query<(&mut Style, &mut UiColor), PanelFilter>,
+    mut portrait_query: Query<&mut Style, PortraitFilter>,
+    mut speaker_query: Query<&mut Text, With<DialogueSpeakerElement>>,
+) {
+    if !theme.is_changed() || ui_data.theme == *theme {
+        return;
+    }
+    *ui_data = UIData::new(theme.clone(), &asset_server);
+    for mut style in root_query.iter_mut() {
+        *style = ui_data.root_style();
+    }
+    for (mut style, mut color) in panel_query.iter_mut() {
+        *style = ui_data.panel_style();
+        *color = ui_data.theme.panel_color.into();
+    }
+    for mut style in portrait_query.iter_mut() {
+        let shown = style.display == Display::Flex;
+        *style = ui_data.portrait_style(shown);
+    }
+    for mut text in speaker_query.iter_mut() {
+        let name_color = text.sections[0].style.color;
+        text.sections[0].style = TextStyle {
+            // keep the current speaker's color
+            color: name_color,
+            ..ui_data.speaker_name_style()
+        };
     }
 }
 
-fn hide_dialogue_ui(mut root_query: Query<&mut Transform, With<DialogueRootElement>>) {
-    for mut root_tx in root_query.iter_mut() {
-        root_tx.scale = Vec3::ZERO;
-    }
+// Identifies a response the same way across dialogue updates
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResponseId {
+    // id of the node the response belongs to
+    pub node: std::string::String,
+    // its position among the node's responses
+    pub index: usize,
 }
 
 #[derive(Clone)]
 pub struct ResponseButtonElementData {
     pub text: std::string::String,
-    pub id: usize,
+    pub id: ResponseId,
     pub skip: bool,
+    // whether the node it leads to has been seen before, in this or an earlier conversation
+    pub seen: bool,
 }
 
-// Used to re-render dialogue over two frames
-struct PostDialogueFlushEvent(UpdateDialogueUIEvent);
+#[derive(Clone)]
+pub struct SpeakerNameData {
+    pub name: std::string::String,
+    pub color: Color,
+}
 
+#[derive(Clone)]
 pub struct UpdateDialogueUIEvent {
+    pub speaker: Option<SpeakerNameData>,
+    // id of the portrait image to show beside the text
+    pub portrait: Option<std::string::String>,
     pub dialogue_text: std::string::String,
+    // whether the continue key does something, shown as a hint below the text
+    pub can_continue: bool,
     pub response_buttons: Vec<ResponseButtonElementData>,
 }
 
-// Catch dialogue updates, flush dialogue in this frame, causing refresh next frame
-fn flush_dialogue_ui(
+fn refresh_dialogue_text_ui(
     mut commands: Commands,
+    mut query: Query<(Entity, &mut Text), With<DialogueTextElement>>,
     mut evr: EventReader<UpdateDialogueUIEvent>,
-    mut evw: EventWriter<PostDialogueFlushEvent>,
-    container_query: Query<Entity, With<ResponseContainerElement>>,
+    mut reveal: ResMut<TextReveal>,
+    settings: Res<DialogueAnimationSettings>,
 ) {
-    // Container may not exist, so only parse the event once it does.
-    for container in container_query.iter() {
-        for ev in evr.iter() {
-            // Clear old response container buttons
-            commands.entity(container).despawn_descendants();
-            // TODO: use system chaining
-            evw.send(PostDialogueFlushEvent(UpdateDialogueUIEvent {
-                dialogue_text: ev.dialogue_text.clone(),
-                response_buttons: ev.response_buttons.clone(),
-            }));
+    // Catch dialogue updates
+    for e in evr.iter() {
+        // each line fades in as it's typed out
+        let fade = animation::Tween::new(0.0, 1.0, settings.text_fade_seconds);
+        reveal.set_opacity(fade.value());
+        for (entity, mut text) in query.iter_mut() {
+            text.sections.truncate(1);
+            text.sections[0].value.clear();
+            commands.entity(entity).insert(fade.clone());
         }
+        // typed out by typewriter::reveal_dialogue_text
+        reveal.start(&e.dialogue_text);
     }
 }
 
-// Refresh dialogue in response to a flush event
-fn refresh_dialogue_response_ui(
-    mut commands: Commands,
-    mut evr: EventReader<PostDialogueFlushEvent>,
-    container_query: Query<Entity, With<ResponseContainerElement>>,
-    ui_data: Res<UIData>,
+fn refresh_dialogue_speaker_ui(
+    mut query: Query<&mut Text, With<DialogueSpeakerElement>>,
+    mut evr: EventReader<UpdateDialogueUIEvent>,
 ) {
-    // Container may not exist, so only parse the event once it does.
-    for container in container_query.iter() {
-        for ev in evr.iter() {
-            println!("Post flsuh");
-            // Add response buttons to container
-            ev.0.response_buttons
-                .iter()
-                .filter(|r| !r.skip)
-                .for_each(|response_button_data| {
-                    commands.entity(container).with_children(|parent| {
-                        parent
-                            .spawn_bundle(ui_data.build_response_button(response_button_data.id))
-                            .with_children(|parent| {
-                                parent.spawn_bundle(ui_data.build_response_button_text(
-                                    response_button_data.text.as_str(),
-                                ));
-                            });
-                    });
-                });
+    for e in evr.iter() {
+        for mut text in query.iter_mut() {
+            match &e.speaker {
+                Some(speaker) => {
+                    text.sections[0].value = speaker.name.clone();
+                    text.sections[0].style.color = speaker.color;
+                }
+                None => text.sections[0].value.clear(),
+            }
         }
     }
 }
 
-fn refresh_dialogue_text_ui(
-    mut query: Query<&mut Text, With<DialogueTextElement>>,
-    mut evr: EventReader<PostDialogueFlushEvent>,
+// Swaps the portrait image as the speaker or their expression changes
+fn refresh_dialogue_portrait_ui(
+    mut query: Query<(&mut UiImage, &mut Style), With<DialoguePortraitElement>>,
+    mut evr: EventReader<UpdateDialogueUIEvent>,
+    asset_server: Res<AssetServer>,
+    ui_data: Res<UIData>,
 ) {
-    // Catch dialogue flush event
     for e in evr.iter() {
-        for mut text in query.iter_mut() {
-            text.sections[0].value = format!("{}", e.0.dialogue_text);
+        for (mut image, mut style) in query.iter_mut() {
+            match &e.portrait {
+                Some(portrait) => {
+                    image.0 =
+                        asset_server.load(format!("{}/{}.png", PORTRAIT_DIR, portrait).as_str());
+                    *style = ui_data.portrait_style(true);
+                }
+                None => style.display = Display::None,
+            }
         }
     }
 }
 
-pub struct ResponseButtonClicked(pub usize);
+pub struct ResponseButtonClicked(pub ResponseId);
 
+// Mouse interactions, hovering a button focuses it like the keyboard would
 fn response_button_interactions(
-    mut interaction_query: Query<
-        (&Interaction, &Children, &ResponseButtonElement),
-        Changed<Interaction>,
-    >,
-    mut text_query: Query<&mut Text>,
+    interaction_query: Query<(&Interaction, &ResponseButtonElement), Changed<Interaction>>,
+    mut focus: ResMut<navigation::ResponseFocus>,
     mut evw: EventWriter<ResponseButtonClicked>,
 ) {
-    for (interaction, children, response_btn) in interaction_query.iter_mut() {
-        let mut text = text_query.get_mut(children[0]).unwrap();
-
+    for (interaction, response_btn) in interaction_query.iter() {
         match *interaction {
             Interaction::Clicked => {
-                evw.send(ResponseButtonClicked(response_btn.response_index));
+                evw.send(ResponseButtonClicked(response_btn.response.clone()));
             }
             Interaction::Hovered => {
-                text.sections[0].style.color = HOVERED_BUTTON.into();
-            }
-            Interaction::None => {
-                text.sections[0].style.color = NORMAL_BUTTON.into();
+                focus.focused = Some(response_btn.response.clone());
             }
+            Interaction::None => {}
         }
     }
 }
@@ -311,20 +536,87 @@ pub struct UIPlugin;
 
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<UIData>()
+        app.add_asset::<DialogueThemeFile>()
+            .init_asset_loader::<theme::DialogueThemeLoader>()
+            .init_resource::<DialogueTheme>()
+            .init_resource::<theme::DialogueThemes>()
+            .add_event::<UseDialogueTheme>()
+            .init_resource::<UIData>()
+            .init_resource::<TypewriterSettings>()
+            .init_resource::<DialogueAnimationSettings>()
+            .init_resource::<TextReveal>()
+            .init_resource::<navigation::ResponseFocus>()
+            .init_resource::<backlog::Backlog>()
+            .add_event::<AppendBacklogEvent>()
+            .add_event::<ShowBarkEvent>()
+            .add_event::<TextRevealFinished>()
             .add_event::<ResponseButtonClicked>()
             .add_event::<UpdateDialogueUIEvent>()
-            .add_event::<PostDialogueFlushEvent>()
             .add_startup_system(setup_dialogue_ui)
+            .add_startup_system(backlog::setup_backlog_ui)
+            .add_startup_system(prompt::setup_interaction_prompt)
+            .add_system_set(
+                SystemSet::on_enter(super::AppState::Dialogue)
+                    .with_system(animation::open_dialogue_ui)
+                    .with_system(bubbles::clear_speech_bubbles),
+            )
+            .add_system_set(
+                SystemSet::on_update(super::AppState::Dialogue)
+                    .with_system(navigation::navigate_responses),
+            )
+            .add_system_set(
+                SystemSet::on_enter(super::AppState::ClosingDialogue)
+                    .with_system(animation::close_dialogue_ui),
+            )
             .add_system_set(
-                SystemSet::on_enter(super::AppState::Dialogue).with_system(show_dialogue_ui),
+                SystemSet::on_update(super::AppState::ClosingDialogue)
+                    .with_system(animation::finish_closing_dialogue),
             )
             .add_system_set(
-                SystemSet::on_exit(super::AppState::Dialogue).with_system(hide_dialogue_ui),
+                SystemSet::on_enter(super::AppState::Game).with_system(animation::hide_dialogue_ui),
+            )
+            // everything below sees a dialogue update on the frame it's sent
+            .add_system(
+                refresh_dialogue_text_ui
+                    .label("ui-dialogue-text")
+                    .after("dialogue-update"),
+            )
+            .add_system(refresh_dialogue_speaker_ui.after("dialogue-update"))
+            .add_system(refresh_dialogue_portrait_ui.after("dialogue-update"))
+            .add_system(
+                typewriter::reveal_dialogue_text
+                    .label("ui-dialogue-reveal")
+                    .after("ui-dialogue-text"),
+            )
+            .add_system(
+                responses::reconcile_dialogue_responses
+                    .after("dialogue-update")
+                    .after("ui-dialogue-reveal"),
+            )
+            .add_system(response_button_interactions)
+            .add_system(theme::select_dialogue_theme.label("ui-theme-select"))
+            .add_system(
+                apply_dialogue_theme
+                    .label("ui-theme-apply")
+                    .after("ui-theme-select"),
+            )
+            .add_system(animation::advance_tweens.label("ui-tween"))
+            .add_system(
+                animation::slide_dialogue_panel
+                    .after("ui-tween")
+                    .after("ui-theme-apply"),
+            )
+            .add_system(
+                animation::fade_dialogue_text
+                    .after("ui-tween")
+                    .before("ui-dialogue-text"),
             )
-            .add_system(flush_dialogue_ui.label("ui-dialogue-flush"))
-            .add_system(refresh_dialogue_response_ui.before("ui-dialogue-flush"))
-            .add_system(refresh_dialogue_text_ui.before("ui-dialogue-flush"))
-            .add_system(response_button_interactions);
+            .add_system(animation::grow_response_buttons.after("ui-tween"))
+            .add_system(navigation::highlight_focused_response)
+            .add_system(bubbles::spawn_speech_bubbles.label("ui-bubbles-spawn"))
+            .add_system(bubbles::update_speech_bubbles.after("ui-bubbles-spawn"))
+            .add_system(prompt::refresh_interaction_prompt)
+            .add_system(backlog::update_backlog.label("ui-backlog-update"))
+            .add_system(backlog::refresh_backlog_ui.after("ui-backlog-update"));
     }
 }
@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use super::{ResponseButtonClicked, ResponseButtonElement, ResponseId, UIData};
+use crate::input;
+
+// Resource holding the response of the button picked by the keyboard, gamepad or mouse
+#[derive(Default)]
+pub(super) struct ResponseFocus {
+    pub focused: Option<ResponseId>,
+}
+
+// Responses of the buttons on screen, in the order they're listed. Buttons wait hidden while
+// the line is still being revealed.
+fn listed_responses(buttons: &Query<(&ResponseButtonElement, &Style)>) -> Vec<ResponseId> {
+    let mut listed: Vec<ResponseId> = buttons
+        .iter()
+        .filter(|(_, style)| style.display != Display::None)
+        .map(|(button, _)| button.response.clone())
+        .collect();
+    listed.sort_unstable_by_key(|response| response.index);
+    listed
+}
+
+// Moves focus between responses and picks them without the mouse
+pub(super) fn navigate_responses(
+    inputs: Res<input::Inputs>,
+    mut focus: ResMut<ResponseFocus>,
+    buttons: Query<(&ResponseButtonElement, &Style)>,
+    mut evw: EventWriter<ResponseButtonClicked>,
+) {
+    let listed = listed_responses(&buttons);
+    if listed.is_empty() {
+        return;
+    }
+
+    if let Some(shortcut) = inputs.response_shortcut {
+        if let Some(response) = listed.get(shortcut) {
+            evw.send(ResponseButtonClicked(response.clone()));
+        }
+        return;
+    }
+
+    let position = focus
+        .focused
+        .as_ref()
+        .and_then(|focused| listed.iter().position(|r| r == focused));
+    if inputs.response_confirm {
+        if let Some(position) = position {
+            evw.send(ResponseButtonClicked(listed[position].clone()));
+        }
+    } else if inputs.response_up {
+        let position = position.map_or(0, |p| p.saturating_sub(1));
+        focus.focused = Some(listed[position].clone());
+    } else if inputs.response_down {
+        let position = position.map_or(0, |p| (p + 1).min(listed.len() - 1));
+        focus.focused = Some(listed[position].clone());
+    }
+}
+
+pub(super) fn highlight_focused_response(
+    focus: Res<ResponseFocus>,
+    ui_data: Res<UIData>,
+    changed: Query<(), Changed<ResponseButtonElement>>,
+    mut buttons: Query<(&ResponseButtonElement, &Children, &mut UiColor)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !focus.is_changed() && !ui_data.is_changed() && changed.is_empty() {
+        return;
+    }
+    for (button, children, mut background) in buttons.iter_mut() {
+        let focused = focus.focused.as_ref() == Some(&button.response);
+        *background = if focused {
+            ui_data.theme.focus_background
+        } else {
+            Color::NONE
+        }
+        .into();
+        if let Ok(mut text) = text_query.get_mut(children[0]) {
+            text.sections[0].style.color = if focused {
+                ui_data.theme.hover_color
+            } else if button.seen {
+                ui_data.theme.hint_color
+            } else {
+                ui_data.theme.button_color
+            };
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use super::UIData;
+use crate::{input, interact};
+
+#[derive(Component)]
+pub(super) struct InteractionPromptElement;
+
+#[derive(Component)]
+pub(super) struct InteractionPromptText;
+
+impl UIData {
+    // Full width strip centring the prompt a little below the middle of the screen
+    fn build_interaction_prompt(&self) -> NodeBundle {
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.0),
+                    bottom: Val::Percent(30.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                justify_content: JustifyContent::Center,
+                display: Display::None,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        }
+    }
+
+    fn interaction_prompt_section(&self, prompt: &str) -> TextSection {
+        TextSection {
+            value: format!("[{:?}] {}", input::INTERACT_KEY, prompt),
+            style: TextStyle {
+                font: self.font_handle.clone(),
+                font_size: self.theme.hint_font_size,
+                color: self.theme.text_color,
+            },
+        }
+    }
+}
+
+pub(super) fn setup_interaction_prompt(mut commands: Commands, ui_data: Res<UIData>) {
+    commands
+        .spawn_bundle(ui_data.build_interaction_prompt())
+        .insert(InteractionPromptElement)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::default())
+                .insert(InteractionPromptText);
+        });
+}
+
+// Shows the targeted entity's prompt while the player is free to interact with it
+pub(super) fn refresh_interaction_prompt(
+    app_state: Res<State<crate::AppState>>,
+    interaction: Res<interact::Interaction>,
+    ui_data: Res<UIData>,
+    prompts: Query<&interact::InteractionPrompt>,
+    mut root_query: Query<&mut Style, With<InteractionPromptElement>>,
+    mut text_query: Query<&mut Text, With<InteractionPromptText>>,
+) {
+    let prompt = interaction
+        .current_interaction
+        .filter(|_| *app_state.current() == crate::AppState::Game)
+        .and_then(|target| prompts.get(target).ok());
+
+    let display = if prompt.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in root_query.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+
+    if let Some(prompt) = prompt {
+        let section = ui_data.interaction_prompt_section(&prompt.0);
+        for mut text in text_query.iter_mut() {
+            let stale = text.sections.first().map_or(true, |current| {
+                current.value != section.value || ui_data.is_changed()
+            });
+            if stale {
+                text.sections = vec![section.clone()];
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::animation::{DialogueAnimationSettings, Tween};
+use super::navigation::ResponseFocus;
+use super::{
+    ResponseButtonElement, ResponseContainerElement, ResponseId, TextReveal, UIData,
+    UpdateDialogueUIEvent,
+};
+
+#[derive(Component)]
+pub(super) struct ContinueHintElement;
+
+// Changes turning the response ids on screen into the ones which should be, both in the order
+// they're listed
+#[derive(Debug, PartialEq)]
+struct ResponseListDiff<T> {
+    removed: Vec<T>,
+    // (position in the new list, id), in order of position
+    added: Vec<(usize, T)>,
+}
+
+fn diff_responses<T: Clone + PartialEq>(on_screen: &[T], shown: &[T]) -> ResponseListDiff<T> {
+    let kept: Vec<&T> = on_screen.iter().filter(|id| shown.contains(id)).collect();
+    let in_order: Vec<&T> = shown.iter().filter(|id| on_screen.contains(id)).collect();
+    // buttons are only ever added or removed, so anything out of order is listed afresh
+    let kept = if kept == in_order { kept } else { Vec::new() };
+    ResponseListDiff {
+        removed: on_screen
+            .iter()
+            .filter(|id| !kept.contains(id))
+            .cloned()
+            .collect(),
+        added: shown
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| !kept.contains(id))
+            .map(|(position, id)| (position, id.clone()))
+            .collect(),
+    }
+}
+
+// Brings the continue hint and response buttons in line with the latest dialogue update in
+// place. Buttons are kept hidden while the text is revealed, and grow in once it has been.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn reconcile_dialogue_responses(
+    mut commands: Commands,
+    mut evr: EventReader<UpdateDialogueUIEvent>,
+    mut latest: Local<Option<UpdateDialogueUIEvent>>,
+    reveal: Res<TextReveal>,
+    ui_data: Res<UIData>,
+    settings: Res<DialogueAnimationSettings>,
+    mut focus: ResMut<ResponseFocus>,
+    container_query: Query<Entity, With<ResponseContainerElement>>,
+    hint_query: Query<Entity, With<ContinueHintElement>>,
+    mut button_query: Query<(
+        Entity,
+        &mut ResponseButtonElement,
+        &Children,
+        &mut Style,
+        Option<&Tween>,
+    )>,
+    mut text_query: Query<&mut Text>,
+) {
+    let mut updated = false;
+    for e in evr.iter() {
+        *latest = Some(e.clone());
+        updated = true;
+    }
+    if !updated && !reveal.is_changed() && !ui_data.is_changed() {
+        return;
+    }
+    // Container may not exist yet
+    let container = match container_query.get_single() {
+        Ok(container) => container,
+        Err(_) => return,
+    };
+    if updated {
+        // a new line starts back at the first response
+        focus.focused = None;
+    }
+
+    let revealed = reveal.is_finished();
+    let (can_continue, shown) = match latest.as_ref() {
+        Some(e) => (
+            e.can_continue && revealed,
+            e.response_buttons.iter().filter(|r| !r.skip).collect(),
+        ),
+        None => (false, Vec::new()),
+    };
+
+    let hint = hint_query.get_single().ok();
+    match (hint, can_continue) {
+        (None, true) => {
+            let hint = commands
+                .spawn_bundle(ui_data.build_continue_hint())
+                .insert(ContinueHintElement)
+                .id();
+            commands.entity(container).insert_children(0, &[hint]);
+        }
+        (Some(hint), false) => commands.entity(hint).despawn_recursive(),
+        _ => {}
+    }
+    // buttons are listed after the hint
+    let first_button = if can_continue { 1 } else { 0 };
+
+    let mut on_screen: Vec<ResponseId> = button_query
+        .iter()
+        .map(|(_, button, _, _, _)| button.response.clone())
+        .collect();
+    on_screen.sort_unstable_by_key(|id| id.index);
+    let shown_ids: Vec<ResponseId> = shown.iter().map(|r| r.id.clone()).collect();
+    let diff = diff_responses(&on_screen, &shown_ids);
+
+    let display = if revealed {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    let grow = |i: usize| {
+        Tween::new(0.0, 1.0, settings.response_seconds)
+            .delayed(settings.response_stagger_seconds * i as f32)
+    };
+    // buttons added while the text was being revealed start growing once it has been
+    let mut growing = 0;
+    let mut kept_text: HashMap<ResponseId, Entity> = HashMap::new();
+    for (entity, mut button, children, mut style, tween) in button_query.iter_mut() {
+        if diff.removed.contains(&button.response) {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let seen = shown.iter().any(|r| r.id == button.response && r.seen);
+        if button.seen != seen {
+            button.seen = seen;
+        }
+        if style.display != display {
+            style.display = display;
+        }
+        if revealed && tween.is_none() {
+            commands.entity(entity).insert(grow(growing));
+            growing += 1;
+        }
+        kept_text.insert(button.response.clone(), children[0]);
+    }
+
+    // the text of a response kept in place may still have changed, e.g. with the theme
+    for (position, response) in shown.iter().enumerate() {
+        let number = Some(position + 1).filter(|n| *n <= 9);
+        if let Some(text_entity) = kept_text.get(&response.id) {
+            if let Ok(mut text) = text_query.get_mut(*text_entity) {
+                let section = ui_data.response_button_section(number, &response.text);
+                let current = &text.sections[0];
+                if current.value != section.value
+                    || current.style.font != section.style.font
+                    || current.style.font_size != section.style.font_size
+                {
+                    // the color belongs to navigation::highlight_focused_response
+                    let color = current.style.color;
+                    text.sections[0] = section;
+                    text.sections[0].style.color = color;
+                }
+            }
+        }
+    }
+    for (position, id) in diff.added {
+        let response = shown[position];
+        let number = Some(position + 1).filter(|n| *n <= 9);
+        let mut bundle = ui_data.build_response_button(id, response.seen);
+        bundle.button_bundle.transform.scale = Vec3::ZERO;
+        bundle.button_bundle.style.display = display;
+        let mut button = commands.spawn_bundle(bundle);
+        button.with_children(|parent| {
+            parent.spawn_bundle(ui_data.build_response_button_text(number, &response.text));
+        });
+        if revealed {
+            button.insert(grow(growing));
+            growing += 1;
+        }
+        let button = button.id();
+        commands
+            .entity(container)
+            .insert_children(first_button + position, &[button]);
+    }
+
+    if focus
+        .focused
+        .as_ref()
+        .map_or(true, |focused| !shown_ids.contains(focused))
+    {
+        focus.focused = shown_ids.first().cloned();
+    }
+}
+
+#[test]
+pub fn test_diff_responses() {
+    // nothing to do
+    assert_eq!(
+        diff_responses(&[0, 1], &[0, 1]),
+        ResponseListDiff {
+            removed: vec![],
+            added: vec![],
+        }
+    );
+    // a once-only response in the middle was used up
+    assert_eq!(
+        diff_responses(&[0, 1, 2], &[0, 2]),
+        ResponseListDiff {
+            removed: vec![1],
+            added: vec![],
+        }
+    );
+    // and one was added back in its place
+    assert_eq!(
+        diff_responses(&[0, 2], &[0, 1, 2]),
+        ResponseListDiff {
+            removed: vec![],
+            added: vec![(1, 1)],
+        }
+    );
+    assert_eq!(
+        diff_responses(&[], &[3, 4]),
+        ResponseListDiff {
+            removed: vec![],
+            added: vec![(0, 3), (1, 4)],
+        }
+    );
+    // reordered responses are listed again from scratch
+    assert_eq!(
+        diff_responses(&[0, 1], &[1, 0]),
+        ResponseListDiff {
+            removed: vec![0, 1],
+            added: vec![(0, 1), (1, 0)],
+        }
+    );
+
+    // responses are told apart by node as well as position
+    let id = |node: &str, index| ResponseId {
+        node: node.to_string(),
+        index,
+    };
+    assert_eq!(
+        diff_responses(&[id("a", 0), id("a", 1)], &[id("b", 0)]),
+        ResponseListDiff {
+            removed: vec![id("a", 0), id("a", 1)],
+            added: vec![(0, id("b", 0))],
+        }
+    );
+}
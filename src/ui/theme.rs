@@ -0,0 +1,361 @@
+// Look of the dialogue box, loaded from .theme.yaml files. Every key is optional, and a
+// speaker's theme only needs the keys it changes from ui/default.theme.yaml, e.g.
+//
+// font: fonts/FiraCode-Regular.ttf
+// font_size: 20
+// text_color: "#e6e6e6"
+// panel:
+//   anchor: bottom        # or top
+//   height: 50            # percent of the screen
+//   color: "#212121"
+//   portrait_size: 128    # pixels
+// responses:
+//   prefix: "> "
+//   hover_color: "#b3b3b3"
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use yaml_rust::{Yaml, YamlLoader};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PanelAnchor {
+    Top,
+    Bottom,
+}
+
+// Resource styling the dialogue box right now
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogueTheme {
+    pub font: std::string::String,
+    // for [b] and [i] markup, the regular font unless set
+    pub bold_font: Option<std::string::String>,
+    pub italic_font: Option<std::string::String>,
+    // so [b] and [i] still stand out while they're drawn in the regular font
+    pub bold_color: Color,
+    pub italic_color: Color,
+    pub font_size: f32,
+    pub text_color: Color,
+    pub name_font_size: f32,
+    pub hint_font_size: f32,
+    pub hint_color: Color,
+
+    pub panel_anchor: PanelAnchor,
+    // percentages of the screen
+    pub panel_height: f32,
+    pub panel_padding: f32,
+    pub panel_color: Color,
+    // width and height of the speaker's portrait, in pixels
+    pub portrait_size: f32,
+
+    // put before responses without a shortcut number
+    pub button_prefix: std::string::String,
+    // number the first nine responses after their shortcut keys
+    pub numbered_buttons: bool,
+    pub button_font_size: f32,
+    pub button_color: Color,
+    pub hover_color: Color,
+    pub focus_background: Color,
+}
+
+impl Default for DialogueTheme {
+    fn default() -> Self {
+        Self {
+            font: "fonts/FiraCode-Regular.ttf".to_string(),
+            bold_font: None,
+            italic_font: None,
+            bold_color: Color::rgb(1.0, 0.86, 0.55),
+            italic_color: Color::rgb(0.68, 0.82, 1.0),
+            font_size: 20.0,
+            text_color: Color::rgb(0.9, 0.9, 0.9),
+            name_font_size: 22.0,
+            hint_font_size: 16.0,
+            hint_color: Color::rgb(0.6, 0.6, 0.6),
+
+            panel_anchor: PanelAnchor::Bottom,
+            panel_height: 50.0,
+            panel_padding: 5.0,
+            panel_color: Color::rgb(0.13, 0.13, 0.13),
+            portrait_size: 128.0,
+
+            button_prefix: "> ".to_string(),
+            numbered_buttons: true,
+            button_font_size: 20.0,
+            button_color: Color::rgb(0.98, 0.98, 0.98),
+            hover_color: Color::rgb(0.70, 0.70, 0.70),
+            focus_background: Color::rgba(1.0, 1.0, 1.0, 0.08),
+        }
+    }
+}
+
+// A .theme.yaml file, kept as written so it can be applied on top of whichever theme it
+// overrides once that has loaded
+#[derive(Debug, TypeUuid)]
+#[uuid = "b8e7a0c4-3f1d-4a5e-9c62-7d0f4e2a9b13"]
+pub struct DialogueThemeFile {
+    source: std::string::String,
+}
+
+impl DialogueTheme {
+    pub fn from_yaml(source: &str) -> Result<Self, anyhow::Error> {
+        Self::from_yaml_over(&Self::default(), source)
+    }
+
+    // The theme in `source`, taking anything it leaves out from `base`
+    pub fn from_yaml_over(base: &DialogueTheme, source: &str) -> Result<Self, anyhow::Error> {
+        let docs = YamlLoader::load_from_str(source)?;
+        let mut theme = base.clone();
+        let yaml = match docs.get(0) {
+            Some(yaml) if !yaml.is_null() => yaml,
+            _ => return Ok(theme),
+        };
+        if yaml.as_hash().is_none() {
+            anyhow::bail!("expected a map of theme settings");
+        }
+
+        read_string(yaml, "", "font", &mut theme.font)?;
+        theme.bold_font = optional_string(yaml, "", "bold_font")?.or(theme.bold_font);
+        theme.italic_font = optional_string(yaml, "", "italic_font")?.or(theme.italic_font);
+        read_color(yaml, "", "bold_color", &mut theme.bold_color)?;
+        read_color(yaml, "", "italic_color", &mut theme.italic_color)?;
+        read_f32(yaml, "", "font_size", &mut theme.font_size)?;
+        read_color(yaml, "", "text_color", &mut theme.text_color)?;
+        read_f32(yaml, "", "name_font_size", &mut theme.name_font_size)?;
+        read_f32(yaml, "", "hint_font_size", &mut theme.hint_font_size)?;
+        read_color(yaml, "", "hint_color", &mut theme.hint_color)?;
+
+        let panel = &yaml["panel"];
+        match panel["anchor"].as_str() {
+            Some("top") => theme.panel_anchor = PanelAnchor::Top,
+            Some("bottom") => theme.panel_anchor = PanelAnchor::Bottom,
+            None if panel["anchor"].is_badvalue() => {}
+            _ => anyhow::bail!("panel.anchor: expected top or bottom"),
+        }
+        read_f32(panel, "panel", "height", &mut theme.panel_height)?;
+        read_f32(panel, "panel", "padding", &mut theme.panel_padding)?;
+        read_color(panel, "panel", "color", &mut theme.panel_color)?;
+        read_f32(panel, "panel", "portrait_size", &mut theme.portrait_size)?;
+
+        let responses = &yaml["responses"];
+        read_string(responses, "responses", "prefix", &mut theme.button_prefix)?;
+        if let Some(numbered) = responses["numbered"].as_bool() {
+            theme.numbered_buttons = numbered;
+        } else if !responses["numbered"].is_badvalue() {
+            anyhow::bail!("responses.numbered: expected true or false");
+        }
+        read_f32(
+            responses,
+            "responses",
+            "font_size",
+            &mut theme.button_font_size,
+        )?;
+        read_color(responses, "responses", "color", &mut theme.button_color)?;
+        read_color(
+            responses,
+            "responses",
+            "hover_color",
+            &mut theme.hover_color,
+        )?;
+        read_color(
+            responses,
+            "responses",
+            "focus_background",
+            &mut theme.focus_background,
+        )?;
+        Ok(theme)
+    }
+}
+
+// Dotted path of a key for error messages, e.g. panel.color
+fn key_path(section: &str, key: &str) -> std::string::String {
+    if section.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", section, key)
+    }
+}
+
+fn optional_string(
+    yaml: &Yaml,
+    section: &str,
+    key: &str,
+) -> Result<Option<std::string::String>, anyhow::Error> {
+    match &yaml[key] {
+        Yaml::BadValue => Ok(None),
+        Yaml::String(s) => Ok(Some(s.clone())),
+        _ => anyhow::bail!("{}: expected a string", key_path(section, key)),
+    }
+}
+
+fn read_string(
+    yaml: &Yaml,
+    section: &str,
+    key: &str,
+    value: &mut std::string::String,
+) -> Result<(), anyhow::Error> {
+    if let Some(s) = optional_string(yaml, section, key)? {
+        *value = s;
+    }
+    Ok(())
+}
+
+fn read_f32(yaml: &Yaml, section: &str, key: &str, value: &mut f32) -> Result<(), anyhow::Error> {
+    match &yaml[key] {
+        Yaml::BadValue => {}
+        Yaml::Integer(i) => *value = *i as f32,
+        Yaml::Real(_) => *value = yaml[key].as_f64().unwrap() as f32,
+        _ => anyhow::bail!("{}: expected a number", key_path(section, key)),
+    }
+    Ok(())
+}
+
+// Colors are written as hex, e.g. "#212121", or "#ffffff14" with alpha
+fn read_color(
+    yaml: &Yaml,
+    section: &str,
+    key: &str,
+    value: &mut Color,
+) -> Result<(), anyhow::Error> {
+    if let Some(hex) = optional_string(yaml, section, key)? {
+        *value = Color::hex(hex.trim_start_matches('#'))
+            .map_err(|_| anyhow::anyhow!("{}: expected a hex color", key_path(section, key)))?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct DialogueThemeLoader;
+
+impl AssetLoader for DialogueThemeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let source = std::str::from_utf8(bytes)?;
+            // broken files are reported here rather than each time they're applied
+            DialogueTheme::from_yaml(source)?;
+            load_context.set_default_asset(LoadedAsset::new(DialogueThemeFile {
+                source: source.to_string(),
+            }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.yaml"]
+    }
+}
+
+// Sent when a conversation starts to switch to a speaker's own theme, None goes back to the
+// default one
+pub struct UseDialogueTheme(pub Option<Handle<DialogueThemeFile>>);
+
+// Resource tracking which theme files make up the DialogueTheme resource
+pub(super) struct DialogueThemes {
+    default: Handle<DialogueThemeFile>,
+    selected: Option<Handle<DialogueThemeFile>>,
+}
+
+impl FromWorld for DialogueThemes {
+    fn from_world(world: &mut World) -> Self {
+        let server = world.get_resource::<AssetServer>().unwrap();
+        Self {
+            default: server.load("ui/default.theme.yaml"),
+            selected: None,
+        }
+    }
+}
+
+// Applies the selected theme over the default one whenever it changes, or either file is
+// (re)loaded
+pub(super) fn select_dialogue_theme(
+    mut evr: EventReader<UseDialogueTheme>,
+    mut asset_evr: EventReader<AssetEvent<DialogueThemeFile>>,
+    theme_files: Res<Assets<DialogueThemeFile>>,
+    mut themes: ResMut<DialogueThemes>,
+    mut theme: ResMut<DialogueTheme>,
+) {
+    let mut dirty = false;
+    for e in evr.iter() {
+        themes.selected = e.0.clone();
+        dirty = true;
+    }
+    for e in asset_evr.iter() {
+        if let AssetEvent::Created { .. } | AssetEvent::Modified { .. } = e {
+            dirty = true;
+        }
+    }
+    if !dirty {
+        return;
+    }
+    let apply = |base: DialogueTheme, handle: &Handle<DialogueThemeFile>| {
+        // a file which hasn't loaded yet is picked up by its Created event
+        match theme_files.get(handle) {
+            Some(file) => DialogueTheme::from_yaml_over(&base, &file.source).unwrap_or_else(|e| {
+                error!("failed to apply dialogue theme: {}", e);
+                base
+            }),
+            None => base,
+        }
+    };
+    let mut selected = apply(DialogueTheme::default(), &themes.default);
+    if let Some(handle) = &themes.selected {
+        selected = apply(selected, handle);
+    }
+    if *theme != selected {
+        *theme = selected;
+    }
+}
+
+#[test]
+pub fn test_theme_from_yaml() {
+    let theme = DialogueTheme::from_yaml(
+        "
+font_size: 24
+bold_color: '#00ff00'
+panel:
+  anchor: top
+  height: 30.5
+  color: '#ff0000'
+  portrait_size: 96
+responses:
+  prefix: '- '
+",
+    )
+    .unwrap();
+    assert_eq!(theme.font_size, 24.0);
+    assert_eq!(theme.bold_color, Color::rgb(0.0, 1.0, 0.0));
+    assert_eq!(theme.panel_anchor, PanelAnchor::Top);
+    assert_eq!(theme.panel_height, 30.5);
+    assert_eq!(theme.panel_color, Color::rgb(1.0, 0.0, 0.0));
+    assert_eq!(theme.portrait_size, 96.0);
+    assert_eq!(theme.button_prefix, "- ");
+    // everything else keeps its default
+    assert_eq!(theme.font, DialogueTheme::default().font);
+    assert_eq!(theme.hover_color, DialogueTheme::default().hover_color);
+
+    assert_eq!(
+        DialogueTheme::from_yaml("").unwrap(),
+        DialogueTheme::default()
+    );
+    let err = DialogueTheme::from_yaml("panel: { color: red }").unwrap_err();
+    assert_eq!(err.to_string(), "panel.color: expected a hex color");
+
+    // speakers' themes build on the default one
+    let base = DialogueTheme {
+        font_size: 30.0,
+        ..DialogueTheme::default()
+    };
+    let theme = DialogueTheme::from_yaml_over(&base, "panel: { height: 20 }").unwrap();
+    assert_eq!(theme.font_size, 30.0);
+    assert_eq!(theme.panel_height, 20.0);
+
+    let default = std::fs::read_to_string("./assets/ui/default.theme.yaml").unwrap();
+    let default = DialogueTheme::from_yaml(&default).unwrap();
+    let cube = std::fs::read_to_string("./assets/ui/cube.theme.yaml").unwrap();
+    DialogueTheme::from_yaml_over(&default, &cube).unwrap();
+}
@@ -0,0 +1,192 @@
+use bevy::prelude::*;
+
+use super::markup::{self, MarkupSpan};
+use super::{DialogueTextElement, UIData};
+
+// Resource controlling how fast dialogue text is revealed
+pub struct TypewriterSettings {
+    // 0 shows text all at once
+    pub chars_per_second: f32,
+    // extra seconds to wait after the end of a sentence or clause
+    pub punctuation_pause: f32,
+}
+
+impl Default for TypewriterSettings {
+    fn default() -> Self {
+        Self {
+            chars_per_second: 40.0,
+            punctuation_pause: 0.25,
+        }
+    }
+}
+
+// Sent once the dialogue text is fully shown, whether it was revealed or skipped
+pub struct TextRevealFinished;
+
+// Resource tracking how much of the dialogue text is on screen
+pub struct TextReveal {
+    spans: Vec<MarkupSpan>,
+    // the text without its markup
+    chars: Vec<char>,
+    revealed: usize,
+    // seconds until the next character appears
+    wait: f32,
+    // whether TextRevealFinished has been sent for the current text
+    announced: bool,
+    // multiplies the alpha of the whole text, for fading it in and out
+    opacity: f32,
+    // whether the text has to be redrawn though no more of it has been revealed
+    dirty: bool,
+}
+
+impl Default for TextReveal {
+    fn default() -> Self {
+        Self {
+            spans: Vec::new(),
+            chars: Vec::new(),
+            revealed: 0,
+            wait: 0.0,
+            announced: true,
+            opacity: 1.0,
+            dirty: false,
+        }
+    }
+}
+
+impl TextReveal {
+    pub fn start(&mut self, text: &str) {
+        self.spans = markup::parse_markup(text);
+        self.chars = self
+            .spans
+            .iter()
+            .flat_map(|span| span.text.chars())
+            .collect();
+        self.revealed = 0;
+        self.wait = 0.0;
+        self.announced = false;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.revealed >= self.chars.len()
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        if self.opacity != opacity {
+            self.opacity = opacity;
+            self.dirty = true;
+        }
+    }
+
+    // Skips to the end of the text
+    pub fn finish(&mut self) {
+        self.revealed = self.chars.len();
+    }
+
+    #[cfg(test)]
+    fn shown(&self) -> std::string::String {
+        self.chars[..self.revealed].iter().collect()
+    }
+
+    // Reveals whatever is due after `delta` seconds, returning whether anything changed
+    fn advance(&mut self, delta: f32, settings: &TypewriterSettings) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+        if settings.chars_per_second <= 0.0 {
+            self.finish();
+            return true;
+        }
+        let before = self.revealed;
+        self.wait -= delta;
+        while self.wait <= 0.0 && !self.is_finished() {
+            let c = self.chars[self.revealed];
+            self.revealed += 1;
+            self.wait += 1.0 / settings.chars_per_second;
+            // pause after punctuation ending a clause, but not inside e.g. "3.5" or "..."
+            let next = self.chars.get(self.revealed);
+            if matches!(c, '.' | ',' | '!' | '?' | ';' | ':')
+                && next.map_or(false, |n| n.is_whitespace())
+            {
+                self.wait += settings.punctuation_pause;
+            }
+        }
+        self.revealed != before
+    }
+
+    // Wavy text has to be redrawn every frame, even once it's all shown
+    fn is_animated(&self) -> bool {
+        self.spans.iter().any(|span| span.style.wave)
+    }
+}
+
+pub(super) fn reveal_dialogue_text(
+    time: Res<Time>,
+    settings: Res<TypewriterSettings>,
+    mut reveal: ResMut<TextReveal>,
+    ui_data: Res<UIData>,
+    mut query: Query<&mut Text, With<DialogueTextElement>>,
+    mut evw: EventWriter<TextRevealFinished>,
+) {
+    if reveal.announced && !reveal.is_animated() && !reveal.dirty {
+        return;
+    }
+    // also catches text finished early with TextReveal::finish
+    let changed = reveal.advance(time.delta_seconds(), &settings)
+        || (reveal.is_finished() && !reveal.announced)
+        || reveal.is_animated()
+        || reveal.dirty;
+    if changed {
+        reveal.dirty = false;
+        let mut sections = markup::markup_sections(
+            &reveal.spans,
+            reveal.revealed,
+            &ui_data,
+            time.seconds_since_startup() as f32,
+        );
+        for section in sections.iter_mut() {
+            let alpha = section.style.color.a() * reveal.opacity;
+            section.style.color.set_a(alpha);
+        }
+        for mut text in query.iter_mut() {
+            text.sections = sections.clone();
+        }
+    }
+    if reveal.is_finished() && !reveal.announced {
+        reveal.announced = true;
+        evw.send(TextRevealFinished);
+    }
+}
+
+#[test]
+pub fn test_text_reveal() {
+    let settings = TypewriterSettings {
+        chars_per_second: 4.0,
+        punctuation_pause: 0.5,
+    };
+    let mut reveal = TextReveal::default();
+    reveal.start("Hi. 3.5");
+    assert!(reveal.advance(0.0, &settings));
+    assert_eq!(reveal.shown(), "H");
+    reveal.advance(0.25, &settings);
+    assert_eq!(reveal.shown(), "Hi");
+    reveal.advance(0.25, &settings);
+    assert_eq!(reveal.shown(), "Hi.");
+    // held on the full stop
+    assert!(!reveal.advance(0.5, &settings));
+    reveal.advance(0.25, &settings);
+    assert_eq!(reveal.shown(), "Hi. ");
+    // but not on a decimal point
+    reveal.advance(0.75, &settings);
+    assert_eq!(reveal.shown(), "Hi. 3.5");
+    assert!(reveal.is_finished());
+
+    // markup isn't typed out
+    reveal.start("[b]Hi[/b]");
+    reveal.advance(0.25, &settings);
+    assert_eq!(reveal.shown(), "Hi");
+
+    reveal.start("Skipped");
+    reveal.finish();
+    assert_eq!(reveal.shown(), "Skipped");
+    assert!(!reveal.advance(1.0, &settings));
+}